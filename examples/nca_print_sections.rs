@@ -1,4 +1,6 @@
-use swonch::{keyset::KEYS, prelude::*, storage::FileStorage};
+use swonch::{
+    containers::nca::HashInfo, keyset::KEYS, prelude::*, storage::FileStorage, Integrity,
+};
 
 fn main() -> SwonchResult<()> {
     env_logger::init();
@@ -7,17 +9,55 @@ fn main() -> SwonchResult<()> {
     let mut args = std::env::args().skip(1);
     let fpath = args.next().expect("needs path to a nca as first argument");
 
-    let nca = FileStorage::open(&fpath)?.map_to_storage::<Nca>(())?;
+    let nca = FileStorage::open(&fpath)?.map_to_storage::<Nca>(Integrity::WarnOnly)?;
 
-    println!("{nca:#?}");
+    println!("{:#?}", nca.header());
     for section in nca.sections() {
-        let mut buf = vec![0; 1024 * 0x20];
-        let dec = section.open_decrypted()?;
-        dec.read_at(0, &mut buf)?;
+        let hdr = section.header();
 
-        //let mut fp =
+        println!("section {}:", section.index());
+        println!("  offset:      {:#x}", section.offset());
+        println!("  type:        {:?}", hdr.fs_type);
+        println!("  encryption:  {:?}", hdr.encryption_type);
+        println!("  generation:  {:#x}", hdr.generation());
+        println!("  secure val:  {:#x}", hdr.secure_value());
 
-        //swonch::utils::dbg_hexdump(std::io::stdout(), &buf);
+        match hdr.hash_info()? {
+            HashInfo::None => println!("  hash:        none"),
+            HashInfo::HierarchicalSha256(hash_data) => {
+                println!("  hash:        {:?}", hdr.hash_type);
+                println!("  master hash: {:?}", hash_data.master_hash);
+                println!("  block size:  {:#x}", hash_data.block_size);
+                for (idx, layer) in hash_data.layers().iter().enumerate() {
+                    println!("  layer {idx}:     {:#x} + {:#x}", layer.offset, layer.size);
+                }
+            }
+            HashInfo::HierarchicalIntegrity(meta_info) => {
+                println!("  hash:        {:?}", hdr.hash_type);
+                println!("  master hash: {:?}", meta_info.master_hash);
+                for (idx, level) in meta_info.levels().iter().enumerate() {
+                    println!(
+                        "  level {idx}:     {:#x} + {:#x}, block size {:#x}",
+                        level.offset,
+                        level.size,
+                        level.block_size()
+                    );
+                }
+            }
+        }
+
+        if let Some(patch_info) = hdr.patch_info()? {
+            println!("  patch info:  {patch_info:#?}");
+        }
+        if let Some(sparse_info) = hdr.sparse_info()? {
+            println!("  sparse info: {sparse_info:#?}");
+        }
+        if let Some(compression_info) = hdr.compression_info()? {
+            println!("  compression: {compression_info:#?}");
+        }
+        if let Some(metadata_hash_info) = hdr.metadata_hash_info()? {
+            println!("  metadata:    {metadata_hash_info:#?}");
+        }
     }
 
     Ok(())
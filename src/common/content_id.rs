@@ -0,0 +1,27 @@
+use core::{fmt, num::ParseIntError};
+
+/// Identifies a single content of a title, NCAs are named after it.
+#[binrw::binrw]
+#[brw(big)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ContentId(pub u128);
+
+impl fmt::Debug for ContentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ContentId({:032x})", &self.0)
+    }
+}
+
+impl fmt::Display for ContentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:032x}", &self.0)
+    }
+}
+
+impl TryFrom<&str> for ContentId {
+    type Error = ParseIntError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        u128::from_str_radix(value, 16).map(Self)
+    }
+}
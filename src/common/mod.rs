@@ -1,4 +1,5 @@
+mod content_id;
 mod program_id;
 mod rights_id;
 
-pub use self::{program_id::ProgramId, rights_id::RightsId};
+pub use self::{content_id::ContentId, program_id::ProgramId, rights_id::RightsId};
@@ -0,0 +1,197 @@
+//! A common directory tree abstraction over the different filesystem containers.
+
+use crate::{storage::Storage, SwonchResult};
+use alloc::{sync::Arc, vec::Vec};
+use bstr::{BString, ByteSlice};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: EntryKind,
+    /// Size of the file in bytes, always 0 for directories.
+    pub size: u64,
+}
+
+impl Metadata {
+    pub fn file(size: u64) -> Self {
+        Self {
+            kind: EntryKind::File,
+            size,
+        }
+    }
+
+    pub fn dir() -> Self {
+        Self {
+            kind: EntryKind::Dir,
+            size: 0,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Dir
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: BString,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum FsError {
+    #[error("no such file or directory: {path}")]
+    NotFound { path: BString },
+
+    #[error("not a directory: {path}")]
+    NotADirectory { path: BString },
+
+    #[error("not a file: {path}")]
+    NotAFile { path: BString },
+
+    #[error("directories are nested too deeply: {path}")]
+    TooDeep { path: BString },
+}
+
+impl FsError {
+    pub(crate) fn not_found(path: &[u8]) -> Self {
+        Self::NotFound { path: path.into() }
+    }
+
+    pub(crate) fn not_a_directory(path: &[u8]) -> Self {
+        Self::NotADirectory { path: path.into() }
+    }
+
+    pub(crate) fn not_a_file(path: &[u8]) -> Self {
+        Self::NotAFile { path: path.into() }
+    }
+}
+
+/// Splits a path into its components, ignoring leading, trailing and duplicate `/`.
+pub(crate) fn path_components(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.split_str("/").filter(|c| !c.is_empty())
+}
+
+/// A read-only tree of directories and files. Paths are `/` separated and relative to the root
+/// of the filesystem, a leading `/` is optional.
+///
+/// ```
+/// use swonch::{containers::FileSystem, prelude::*};
+///
+/// fn extract_all(fs: &impl FileSystem) -> SwonchResult<()> {
+///     for entry in fs.walk() {
+///         let (path, metadata) = entry?;
+///
+///         if metadata.is_file() {
+///             let data = fs.open_file(&path)?;
+///             println!("{path} [{} bytes]", data.length()?);
+///         }
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub trait FileSystem {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage>;
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>>;
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata>;
+
+    fn exists(&self, path: &[u8]) -> bool {
+        self.metadata(path).is_ok()
+    }
+
+    /// Recursively iterates over every directory and file, depth first.
+    fn walk(&self) -> Walk<'_>
+    where
+        Self: Sized,
+    {
+        Walk::new(self)
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for Arc<F> {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+        (**self).open_file(path)
+    }
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+        (**self).read_dir(path)
+    }
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+        (**self).metadata(path)
+    }
+}
+
+/// Recursive iterator over all entries of a [`FileSystem`], yielding their full paths.
+///
+/// Directories nested [`Walk::MAX_DEPTH`] levels deep are yielded as [`FsError::TooDeep`] instead
+/// of being descended into, a walk over cyclic links would never end otherwise.
+pub struct Walk<'a> {
+    fs: &'a dyn FileSystem,
+    /// Paths still to be visited, with how deep they are nested.
+    stack: Vec<(BString, Metadata, usize)>,
+    started: bool,
+}
+
+impl<'a> Walk<'a> {
+    pub const MAX_DEPTH: usize = 0x40;
+
+    pub fn new(fs: &'a dyn FileSystem) -> Self {
+        Self {
+            fs,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    fn push_children(&mut self, dir: &[u8], depth: usize) -> SwonchResult<()> {
+        let entries = self.fs.read_dir(dir)?;
+
+        // reversed so popping the stack yields entries in directory order
+        for entry in entries.into_iter().rev() {
+            let mut path = BString::from(dir);
+            path.push(b'/');
+            path.extend_from_slice(&entry.name);
+            self.stack.push((path, entry.metadata, depth));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = SwonchResult<(BString, Metadata)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            if let Err(e) = self.push_children(b"", 1) {
+                return Some(Err(e));
+            }
+        }
+
+        let (path, metadata, depth) = self.stack.pop()?;
+        if metadata.is_dir() {
+            if depth >= Self::MAX_DEPTH {
+                return Some(Err(FsError::TooDeep { path }.into()));
+            }
+            if let Err(e) = self.push_children(&path, depth + 1) {
+                return Some(Err(e));
+            }
+        }
+
+        Some(Ok((path, metadata)))
+    }
+}
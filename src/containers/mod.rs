@@ -1,9 +1,8 @@
+pub mod fs;
 pub mod nand;
 pub mod nca;
 pub mod partitionfs;
+pub mod romfs;
+pub mod xci;
 
-pub trait FileSystem {
-    type DirEntry;
-
-    fn root(&self) -> Self::DirEntry;
-}
+pub use fs::FileSystem;
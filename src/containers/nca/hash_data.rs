@@ -0,0 +1,144 @@
+use crate::utils::HexArray;
+
+/// The hash data of a section, see [`FsHeader::hash_info`](super::FsHeader::hash_info).
+/// The SHA3-256 hash types share the layout with their SHA-256 counterparts.
+#[derive(Debug, Clone)]
+pub enum HashInfo {
+    None,
+    HierarchicalSha256(HierarchicalSha256Data),
+    HierarchicalIntegrity(IntegrityMetaInfo),
+}
+
+/// A range inside of a section.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Largest hash block size that is accepted, a whole block gets buffered when it is verified.
+pub const MAX_HASH_BLOCK_SIZE: u32 = 0x100000;
+
+/// Hash data of [`HashType::HierarchicalSha256Hash`](super::HashType) sections, usually PartitionFS.
+///
+/// Every layer is a table of hashes over the blocks of the next one, the last layer is the
+/// filesystem data and the first one is covered by the master hash.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct HierarchicalSha256Data {
+    pub master_hash: HexArray<0x20>,
+    #[br(assert(
+        (1..=MAX_HASH_BLOCK_SIZE).contains(&block_size),
+        "hierarchical sha256 block size {block_size:#x} is out of range"
+    ))]
+    pub block_size: u32,
+    #[br(assert(
+        (1..=5).contains(&layer_count),
+        "hierarchical sha256 layer count {layer_count} is out of range"
+    ))]
+    pub layer_count: u32,
+    layer_regions: [Region; 5],
+}
+
+impl HierarchicalSha256Data {
+    pub fn layers(&self) -> &[Region] {
+        &self.layer_regions[..self.layer_count as usize]
+    }
+
+    /// The region containing the actual filesystem data.
+    pub fn data_region(&self) -> Region {
+        self.layer_regions[self.layer_count as usize - 1]
+    }
+}
+
+/// A level of an IVFC hash tree.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IvfcLevel {
+    pub offset: u64,
+    pub size: u64,
+    #[br(assert(
+        block_size_log2 <= MAX_HASH_BLOCK_SIZE.ilog2(),
+        "IVFC block size log2 {block_size_log2} is out of range"
+    ))]
+    pub block_size_log2: u32,
+    reserved: u32,
+}
+
+impl IvfcLevel {
+    pub fn block_size(&self) -> u64 {
+        1 << self.block_size_log2
+    }
+
+    pub fn region(&self) -> Region {
+        Region {
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+/// Hash data of [`HashType::HierarchicalIntegrityHash`](super::HashType) sections, usually RomFS.
+///
+/// The master hash covers the first level, every level is a table of hashes over the blocks of the
+/// next one and the last level contains the filesystem data.
+#[binrw::binrw]
+#[brw(little, magic = b"IVFC")]
+#[derive(Debug, Clone)]
+pub struct IntegrityMetaInfo {
+    pub version: u32,
+    pub master_hash_size: u32,
+    /// Number of levels including the master hash.
+    #[br(assert(
+        (2..=7).contains(&level_count),
+        "IVFC level count {level_count} is out of range"
+    ))]
+    pub level_count: u32,
+    levels: [IvfcLevel; 6],
+    pub signature_salt: HexArray<0x20>,
+    pub master_hash: HexArray<0x20>,
+}
+
+impl IntegrityMetaInfo {
+    pub fn levels(&self) -> &[IvfcLevel] {
+        &self.levels[..self.level_count as usize - 1]
+    }
+
+    /// The level containing the actual filesystem data.
+    pub fn data_level(&self) -> IvfcLevel {
+        self.levels[self.level_count as usize - 2]
+    }
+}
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MetaDataHashType {
+    None = 0,
+    HierarchicalIntegrity = 1,
+}
+
+/// Location and hash of the [`MetaDataHashData`] of a section, 14.0.0+.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct MetaDataHashInfo {
+    pub offset: u64,
+    pub size: u64,
+    pub hash: HexArray<0x20>,
+}
+
+/// An IVFC tree over the patch and compression tables of a section, so they can be verified
+/// before being used. The level offsets are relative to [`MetaDataHashData::layer_info_offset`].
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct MetaDataHashData {
+    pub layer_info_offset: u64,
+    pub integrity_meta_info: IntegrityMetaInfo,
+}
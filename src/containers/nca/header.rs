@@ -1,4 +1,8 @@
-use crate::{keyset::KEYS, SwonchResult};
+use crate::{
+    keyset::{Aes128Key, Aes128XtsKey, KeyError, Rsa2048Modulus, KEYS},
+    SwonchResult,
+};
+use aes::cipher::{BlockDecryptMut, KeyInit};
 use binrw::{io::Cursor, BinRead};
 use core::fmt;
 use xts_mode::Xts128;
@@ -143,6 +147,8 @@ pub struct NcaHeader {
     pub fs_entries: [NcaFsEntry; 4],
     pub fs_entry_hashes: [HexArray<0x20>; 4],
     pub encrypted_key_area: [HexArray<0x10>; 4],
+    // NCA0 uses this together with the key area for its RSA encrypted keys
+    reserved1: HexArray<0xc0>,
 }
 
 impl NcaHeader {
@@ -171,6 +177,77 @@ impl NcaHeader {
         }
     }
 
+    /// Decrypts the key area with `key_area_key_{application,ocean,system}_XX`, selected by the
+    /// [`KeyAreaEncryptionKeyIndex`] and key generation of this NCA.
+    ///
+    /// NCA0s may have an RSA-OAEP encrypted key area instead, see [`NcaHeader::decrypt_nca0_key_area`].
+    pub fn decrypt_key_area(&self) -> Result<[[u8; 0x10]; 4], KeyError> {
+        if let NcaMagic::Nca0 = self.magic {
+            if let Some(keys) = self.decrypt_nca0_key_area()? {
+                return Ok(keys);
+            }
+        }
+
+        let key_area_key = KEYS.get_key_index::<Aes128Key>(
+            format!("key_area_key_{}", self.key_area_encryption_key_index),
+            self.get_key_generation_index(),
+        )?;
+
+        let mut aes_ctx = ecb::Decryptor::<aes::Aes128>::new(&key_area_key.0.into());
+        let mut keys = self.encrypted_key_area.clone().map(|k| k.0);
+        for key in keys.iter_mut() {
+            aes_ctx.decrypt_block_mut(key.into());
+        }
+
+        Ok(keys)
+    }
+
+    /// Decrypts the RSA-OAEP encrypted key area used by prerelease NCA0s, which spans the whole
+    /// 0x100 bytes from the key area on, with `beta_nca0_{modulus,exponent,label_hash}`. Only the
+    /// first two keys are set, forming the AES-XTS key for the sections.
+    ///
+    /// Fails with [`KeyError::MissingKey`] if the keys are missing, `None` if the key area isn't
+    /// RSA encrypted.
+    pub fn decrypt_nca0_key_area(&self) -> Result<Option<[[u8; 0x10]; 4]>, KeyError> {
+        let modulus = KEYS.get_key::<Rsa2048Modulus>("beta_nca0_modulus")?;
+        let exponent = KEYS.get_key::<[u8; 0x100]>("beta_nca0_exponent")?;
+        let label_hash = KEYS.get_key::<[u8; 0x20]>("beta_nca0_label_hash")?;
+
+        let mut encrypted = [0; 0x100];
+        for (dst, src) in encrypted
+            .chunks_mut(0x10)
+            .zip(self.encrypted_key_area.iter().map(|k| &k.0[..]))
+        {
+            dst.copy_from_slice(src);
+        }
+        encrypted[0x40..].copy_from_slice(&self.reserved1.0);
+
+        let decrypted = utils::decrypt_rsa2048_oaep(&modulus.0, &exponent, &label_hash, &encrypted)
+            .filter(|decrypted| decrypted.len() >= 0x20);
+        let Some(decrypted) = decrypted else {
+            log::debug!("the NCA0 key area isn't RSA encrypted, assuming an AES encrypted one");
+            return Ok(None);
+        };
+
+        let mut keys = [[0; 0x10]; 4];
+        for (key, src) in keys.iter_mut().zip(decrypted[..0x20].chunks(0x10)) {
+            key.copy_from_slice(src);
+        }
+
+        Ok(Some(keys))
+    }
+
+    /// The AES-XTS key of the sections, made up of the first two keys of the key area.
+    pub fn decrypt_xts_key(&self) -> Result<Aes128XtsKey, KeyError> {
+        let keys = self.decrypt_key_area()?;
+
+        let mut key = [0; 0x20];
+        key[..0x10].copy_from_slice(&keys[0]);
+        key[0x10..].copy_from_slice(&keys[1]);
+
+        Ok(Aes128XtsKey(key))
+    }
+
     pub(crate) fn get_key_generation_index(&self) -> u8 {
         core::cmp::max(self.key_generation, self.key_generation_old).saturating_sub(1)
     }
@@ -1,5 +1,10 @@
 use crate::{
-    keyset::KEYS,
+    containers::{
+        fs::{self, DirEntry, FileSystem, FsError, Metadata},
+        partitionfs::pfs0::Pfs0,
+    },
+    formats::npdm::Npdm,
+    keyset::{Rsa2048Modulus, KEYS},
     prelude::IStorage,
     storage::{FromStorage, Storage},
     utils::{self, HexArray},
@@ -10,6 +15,8 @@ use alloc::{sync::Arc, vec::Vec};
 use binrw::{io::Cursor, BinRead};
 use xts_mode::Xts128;
 
+pub mod hash_data;
+pub use hash_data::*;
 pub mod header;
 pub use header::*;
 pub mod section;
@@ -20,6 +27,8 @@ pub struct Nca {
     storage: Storage,
     header: Arc<NcaHeader>,
     fs_headers: [Option<Arc<FsHeader>>; 4],
+    integrity: Integrity,
+    metadata_verified: [Arc<spin::Once>; 4],
 }
 
 impl Nca {
@@ -29,21 +38,152 @@ impl Nca {
 
     pub fn sections(self: &Arc<Self>) -> impl Iterator<Item = NcaSection> {
         // collect is needed because the iterator captures a lifetime otherwise
-        let sections = self
-            .fs_headers
-            .iter()
-            .enumerate()
-            .flat_map(|(idx, hdr)| hdr.as_ref().map(|hdr| (idx, hdr)))
-            .map(|(index, fs_hdr)| NcaSection {
-                parent: self.storage.clone(),
-                parent_hdr: self.header.clone(),
-                fs_header: fs_hdr.clone(),
-                index: index as u32,
-            })
+        let sections = (0..self.fs_headers.len())
+            .flat_map(|idx| self.section(idx))
             .collect::<Vec<_>>();
 
         sections.into_iter()
     }
+
+    pub fn section(&self, index: usize) -> Option<NcaSection> {
+        let fs_hdr = self.fs_headers.get(index)?.as_ref()?;
+
+        Some(NcaSection {
+            parent: self.storage.clone(),
+            parent_hdr: self.header.clone(),
+            fs_header: fs_hdr.clone(),
+            index: index as u32,
+            integrity: self.integrity,
+            metadata_verified: self.metadata_verified[index].clone(),
+        })
+    }
+
+    /// Verifies the RSA-2048 PSS signatures over the header. The first one is checked against the
+    /// `nca_hdr_fixed_key_modulus_XX` of the keyset selected by the signature key generation, for
+    /// program NCAs the second one against the ACID public key in the `main.npdm` of the ExeFS.
+    ///
+    /// Invalid signatures are handled according to the [`Integrity`] the NCA was opened with.
+    pub fn verify_signatures(&self) -> SwonchResult<()> {
+        let check = |valid: bool, err: NcaError| match (valid, self.integrity) {
+            (true, _) => Ok(()),
+            (false, Integrity::WarnOnly) => {
+                log::error!("{err}");
+                Ok(())
+            }
+            (false, Integrity::ErrorOnMismatch) => Err(err),
+        };
+
+        // the signatures cover the decrypted header, starting at the magic
+        let mut buf = [0; 0x400];
+        self.storage.read_at(0, &mut buf)?;
+        NcaHeader::from_buf(&mut buf)?;
+        let signed = &buf[0x200..];
+
+        let modulus = KEYS.get_key_index::<Rsa2048Modulus>(
+            "nca_hdr_fixed_key_modulus",
+            self.header.signature_key_generation,
+        )?;
+        check(
+            utils::verify_rsa2048_pss(&modulus.0, signed, &self.header.fixed_key_hdr_signature.0),
+            NcaError::FixedKeySignatureInvalid,
+        )?;
+
+        if matches!(self.header.content_type, ContentType::Program) {
+            check(
+                utils::verify_rsa2048_pss(
+                    &self.acid_public_key()?,
+                    signed,
+                    &self.header.npdm_hdr_signature.0,
+                ),
+                NcaError::NpdmSignatureInvalid,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The public key of the ACID in the `main.npdm` of the ExeFS.
+    fn acid_public_key(&self) -> SwonchResult<[u8; 0x100]> {
+        let exefs = self
+            .open_file(b"section0/pfs0")?
+            .map_to_storage::<Pfs0>(())?;
+        let npdm = exefs
+            .open_file(b"main.npdm")
+            .map_err(|_| NcaError::MissingNpdm)?
+            .map_to_storage::<Npdm>(())?;
+
+        Ok(npdm.acid().header.public_key.0)
+    }
+
+    /// Parses a `section<N>` path component into a section.
+    fn section_by_name(&self, name: &[u8]) -> Option<NcaSection> {
+        let index = name.strip_prefix(b"section")?;
+        match index {
+            [idx @ b'0'..=b'3'] => self.section((idx - b'0') as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Exposes every active section as a `section<N>` directory containing the filesystem data
+/// of the section as either a `romfs` or `pfs0` file.
+impl FileSystem for Nca {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+        let mut components = fs::path_components(path);
+
+        match (components.next(), components.next(), components.next()) {
+            (Some(section), Some(name), None) => {
+                let section = self
+                    .section_by_name(section)
+                    .ok_or_else(|| FsError::not_found(path))?;
+
+                match section.fs_data_name().as_bytes() == name {
+                    true => section.open_fs_data(),
+                    false => Err(FsError::not_found(path).into()),
+                }
+            }
+            (Some(_), None, _) | (None, ..) => Err(FsError::not_a_file(path).into()),
+            _ => Err(FsError::not_found(path).into()),
+        }
+    }
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+        let mut components = fs::path_components(path);
+
+        match (components.next(), components.next()) {
+            (None, _) => Ok((0..self.fs_headers.len())
+                .filter(|idx| self.fs_headers[*idx].is_some())
+                .map(|idx| DirEntry {
+                    name: format!("section{idx}").into(),
+                    metadata: Metadata::dir(),
+                })
+                .collect()),
+            (Some(section), None) => {
+                let section = self
+                    .section_by_name(section)
+                    .ok_or_else(|| FsError::not_found(path))?;
+
+                Ok(vec![DirEntry {
+                    name: section.fs_data_name().into(),
+                    metadata: self.metadata(path)?,
+                }])
+            }
+            _ => Err(FsError::not_a_directory(path).into()),
+        }
+    }
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+        let mut components = fs::path_components(path);
+
+        match (components.next(), components.next()) {
+            (None, _) => Ok(Metadata::dir()),
+            (Some(section), None) => self
+                .section_by_name(section)
+                .map(|_| Metadata::dir())
+                .ok_or_else(|| FsError::not_found(path).into()),
+            (Some(_), Some(_)) => Ok(Metadata::file(self.open_file(path)?.length()?)),
+        }
+    }
 }
 
 #[derive(Debug, thiserror_no_std::Error)]
@@ -54,6 +194,34 @@ pub enum NcaError {
     #[error("header seems to be corrupted")]
     HeaderCorrupted,
 
+    #[error("the hash data in an FsHeader is corrupted")]
+    HashDataCorrupted,
+
+    #[error("unsupported hash type {0:?}")]
+    UnsupportedHashType(HashType),
+
+    #[error("unsupported encryption type {0:?}")]
+    UnsupportedEncryptionType(EncryptionType),
+
+    #[error("section {index} is not a patch section")]
+    NotAPatchSection { index: u32 },
+
+    #[error("hash mismatch on the metadata hash data of section {index}")]
+    MetaDataHashMismatch {
+        hash_in_header: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+        index: u8,
+    },
+
+    #[error("the header signature made with the fixed key is invalid")]
+    FixedKeySignatureInvalid,
+
+    #[error("the header signature made with the ACID key of the NPDM is invalid")]
+    NpdmSignatureInvalid,
+
+    #[error("program NCA has no main.npdm in its ExeFS")]
+    MissingNpdm,
+
     #[error("hash mismatch on an FsEntry header")]
     FsEntryHeaderHashMismatch {
         hash_in_header: [u8; 0x20],
@@ -72,34 +240,43 @@ impl FromStorage for Nca {
 
         let hdr = NcaHeader::from_buf(&mut buf[..0x400 as usize])?;
 
-        let fs_header_area = &mut buf[0x400..][..0x200 * 4];
+        let fs_header_area = &buf[0x400..][..0x200 * 4];
         let mut fs_headers = [None, None, None, None];
+        let nca_ver: u8 = hdr.magic.into();
 
-        for idx in 0..4 {
-            if !hdr.fs_entries.get(idx).unwrap().is_active() {
+        for (idx, fs_entry) in hdr.fs_entries.iter().enumerate() {
+            if !fs_entry.is_active() {
                 continue;
             }
 
-            let fs_header = &mut fs_header_area[0x200 * idx..][..0x200];
-
-            // FsHeader.version is apparently always 2, check that to see whether we're encrypted
-            if u32::from_le_bytes(fs_header[..4].try_into().unwrap()) != 2 {
-                // pre 1.0.0 (NCA3) ncas reset the sector index for each fs header
-                // TODO: test this, as i dont have a NCA2 or earlier nca
-                let nca_ver: u8 = hdr.magic.into();
-                let sector = match nca_ver {
-                    3.. => 2 + idx as u128,
-                    ..=2 => {
-                        log::warn!("NCA2 and earlier is untested, if this works or causes issues id love to hear some feedback!");
-                        0
-                    }
-                };
+            // NCA0 keeps the fs headers at the start of their sections instead of behind the NCA header
+            let section_offset = fs_entry.start_offset_block as u64 * 0x200;
+            let mut fs_header = [0; 0x200];
+            match nca_ver {
+                0 => _ = parent.read_at(section_offset, &mut fs_header)?,
+                _ => fs_header.copy_from_slice(&fs_header_area[0x200 * idx..][..0x200]),
+            }
 
-                let xts = KEYS
-                    .get_key::<crate::keyset::Aes128XtsKey>("header_key")
-                    .map(Into::<Xts128<_>>::into)?;
+            if !is_plaintext_fs_header(&fs_header) {
+                // NCA3 numbers the sectors from the start of the NCA, NCA2 and earlier reset the
+                // sector index for each fs header and NCA0 encrypts them like the section data
+                let (xts, sector) = match nca_ver {
+                    3.. => (
+                        KEYS.get_key::<crate::keyset::Aes128XtsKey>("header_key")?,
+                        2 + idx as u128,
+                    ),
+                    1..=2 => (
+                        KEYS.get_key::<crate::keyset::Aes128XtsKey>("header_key")?,
+                        0,
+                    ),
+                    0 => (
+                        hdr.decrypt_xts_key()?,
+                        (section_offset.saturating_sub(0x400) / 0x200) as u128,
+                    ),
+                };
 
-                xts.decrypt_sector(fs_header, utils::aes_xtsn_tweak(sector))
+                Into::<Xts128<_>>::into(xts)
+                    .decrypt_sector(&mut fs_header, utils::aes_xtsn_tweak(sector))
             }
 
             // validate current FsHeader hash
@@ -129,6 +306,491 @@ impl FromStorage for Nca {
             storage: parent,
             header: Arc::new(hdr),
             fs_headers,
+            integrity,
+            metadata_verified: Default::default(),
         }))
     }
 }
+
+/// FsHeader.version is apparently always 2, check that to see whether we're encrypted. Only the
+/// u16 version is compared, the fs type and hash type right behind it differ between sections.
+fn is_plaintext_fs_header(fs_header: &[u8; 0x200]) -> bool {
+    u16::from_le_bytes([fs_header[0], fs_header[1]]) == 2
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        storage::{integrity::IntegrityError, VecStorage},
+        SwonchError,
+    };
+    use aes::cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+    use sha2::Digest;
+
+    pub(crate) const KEY_AREA_KEY: [u8; 0x10] = *b"key_area_key_app";
+    const SECTION_KEY: [u8; 0x10] = *b"aes-ctr-sec-key!";
+
+    /// Builds a plaintext NCA3 with a single AES-CTR PartitionFS section right after the header.
+    pub(crate) fn build_nca(section: &[u8]) -> Vec<u8> {
+        let mut nca = vec![0; 0xc00];
+
+        let hdr = &mut nca[0x200..0x400];
+        hdr[..4].copy_from_slice(b"NCA3");
+        hdr[0x40..0x44].copy_from_slice(&6u32.to_le_bytes());
+        hdr[0x44..0x48].copy_from_slice(&(6 + section.len() as u32 / 0x200).to_le_bytes());
+        hdr[0x48] = 1;
+
+        let mut key = SECTION_KEY.into();
+        aes::Aes128::new(&KEY_AREA_KEY.into()).encrypt_block(&mut key);
+        hdr[0x120..0x130].copy_from_slice(&key);
+
+        let fs_hdr = &mut nca[0x400..0x600];
+        fs_hdr[..2].copy_from_slice(&2u16.to_le_bytes());
+        fs_hdr[2] = FsType::PartitionFS as u8;
+        fs_hdr[3] = HashType::None as u8;
+        fs_hdr[4] = EncryptionType::AesCtr as u8;
+        fs_hdr[0x140..0x144].copy_from_slice(&0x11223344u32.to_le_bytes());
+        fs_hdr[0x144..0x148].copy_from_slice(&0x55667788u32.to_le_bytes());
+
+        let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+        nca[0x280..0x2a0].copy_from_slice(&hash);
+
+        // counter is secure value, generation and the block index of the section, big endian
+        let mut iv = [0; 0x10];
+        iv[..8].copy_from_slice(&[0x55, 0x66, 0x77, 0x88, 0x11, 0x22, 0x33, 0x44]);
+        iv[8..].copy_from_slice(&(0xc00u64 >> 4).to_be_bytes());
+        let mut data = section.to_vec();
+        ctr::Ctr64BE::<aes::Aes128>::new(&SECTION_KEY.into(), &iv.into())
+            .apply_keystream(&mut data);
+
+        nca.extend(data);
+        nca
+    }
+
+    #[test]
+    fn decrypt_aes_ctr_section() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        let plaintext: Vec<u8> = (0..0x400u32).map(|i| (i * 7) as u8).collect();
+        let nca = VecStorage::new(build_nca(&plaintext))
+            .map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+        let section = nca.section(0).expect("section 0 is active");
+
+        let mut buf = vec![0; plaintext.len()];
+        section.open_decrypted()?.read_at(0, &mut buf)?;
+        assert_eq!(buf, plaintext);
+
+        // reads not starting on a block boundary
+        let mut buf = [0; 0x20];
+        section.open_decrypted()?.read_at(0x1f3, &mut buf)?;
+        assert_eq!(buf, plaintext[0x1f3..][..0x20]);
+
+        Ok(())
+    }
+
+    // a hash table in front of the data, the table itself is stored unencrypted
+    fn build_skip_layer_hash_nca(data: &[u8]) -> Vec<u8> {
+        let hash_table = sha2::Sha256::digest(data);
+        let mut section = vec![0; 0x200];
+        section[..0x20].copy_from_slice(&hash_table);
+        section.extend(data);
+
+        let mut nca = build_nca(&section);
+        nca[0xc00..0xc20].copy_from_slice(&hash_table);
+
+        let fs_hdr = &mut nca[0x400..0x600];
+        fs_hdr[3] = HashType::HierarchicalSha256Hash as u8;
+        fs_hdr[4] = EncryptionType::AesCtrSkipLayerHash as u8;
+        let hash_data = &mut fs_hdr[8..];
+        hash_data[..0x20].copy_from_slice(&sha2::Sha256::digest(hash_table));
+        hash_data[0x20..0x24].copy_from_slice(&0x200u32.to_le_bytes());
+        hash_data[0x24..0x28].copy_from_slice(&2u32.to_le_bytes());
+        for (idx, (offset, size)) in [(0u64, 0x20u64), (0x200, 0x200)].iter().enumerate() {
+            let region = &mut hash_data[0x28 + idx * 0x10..];
+            region[..8].copy_from_slice(&offset.to_le_bytes());
+            region[8..0x10].copy_from_slice(&size.to_le_bytes());
+        }
+        let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+        nca[0x280..0x2a0].copy_from_slice(&hash);
+        nca
+    }
+
+    #[test]
+    fn decrypt_skip_layer_hash_section() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        let data: Vec<u8> = (0..0x200u32).map(|i| (i * 5) as u8).collect();
+        let nca = VecStorage::new(build_skip_layer_hash_nca(&data))
+            .map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+        let section = nca.section(0).expect("section 0 is active");
+
+        let mut buf = vec![0; data.len()];
+        section.open_verified_fs_data()?.read_at(0, &mut buf)?;
+        assert_eq!(buf, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_corrupted_sha256_hash_data() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        // hash data offsets inside of the fs header: block size and the size of the first layer
+        for (offset, value) in [
+            (0x28, 0u64),
+            (0x28, MAX_HASH_BLOCK_SIZE as u64 + 1),
+            (0x38, u64::MAX),
+            (0x38, 0x1000),
+        ] {
+            let mut nca = build_skip_layer_hash_nca(&[0; 0x200]);
+            let field = &mut nca[0x400 + offset..];
+            match offset {
+                0x28 => field[..4].copy_from_slice(&(value as u32).to_le_bytes()),
+                _ => field[..8].copy_from_slice(&value.to_le_bytes()),
+            }
+            let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+            nca[0x280..0x2a0].copy_from_slice(&hash);
+
+            let nca = VecStorage::new(nca).map_to_storage::<Nca>(Integrity::WarnOnly)?;
+            let section = nca.section(0).expect("section 0 is active");
+            assert!(matches!(
+                section.open_verified_fs_data(),
+                Err(SwonchError::Nca(NcaError::HashDataCorrupted))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_sparse_section() -> SwonchResult<()> {
+        use crate::storage::{crypto::aes_raw::ctr::nca_ctr_iv, indirect};
+
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        // only the middle block of the section is stored, followed by the sparse table
+        let plaintext: Vec<u8> = (0..0x200u32).map(|i| (i * 13) as u8).collect();
+        let mut data = plaintext.clone();
+        let iv = nca_ctr_iv(0x55667788, 0x11223344, 0xe00);
+        ctr::Ctr64BE::<aes::Aes128>::new(&SECTION_KEY.into(), &iv.into())
+            .apply_keystream(&mut data);
+
+        let mut table =
+            indirect::tests::build_table(&[(0, 0, 1), (0x200, 0, 0), (0x400, 0, 1)], 0x600);
+        let iv = nca_ctr_iv(0x55667788, 3 << 16, 0xe00);
+        ctr::Ctr64BE::<aes::Aes128>::new(&SECTION_KEY.into(), &iv.into())
+            .apply_keystream(&mut table);
+
+        let mut nca = build_nca(&[0; 0x600]);
+        nca.truncate(0xc00);
+        nca.extend(&data);
+        nca.extend(&table);
+
+        let sparse_info = &mut nca[0x400 + 0x148..0x400 + 0x178];
+        sparse_info[..8].copy_from_slice(&0x200u64.to_le_bytes());
+        sparse_info[8..0x10].copy_from_slice(&(table.len() as u64).to_le_bytes());
+        sparse_info[0x10..0x20].copy_from_slice(b"BKTR\x01\0\0\0\x03\0\0\0\0\0\0\0");
+        sparse_info[0x20..0x28].copy_from_slice(&0xc00u64.to_le_bytes());
+        sparse_info[0x28..0x2a].copy_from_slice(&3u16.to_le_bytes());
+        let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+        nca[0x280..0x2a0].copy_from_slice(&hash);
+
+        let nca = VecStorage::new(nca).map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+        let section = nca.section(0).expect("section 0 is active");
+        assert!(section.header().sparse_info()?.is_some());
+
+        // the holes read as zeros, around the stored data
+        let encrypted = section.open_encrypted()?;
+        assert_eq!(encrypted.length()?, 0x600);
+        let mut buf = vec![0; 0x400];
+        assert_eq!(encrypted.read_at(0x100, &mut buf)?, 0x400);
+        assert!(buf[..0x100].iter().all(|b| *b == 0));
+        assert_eq!(buf[0x100..0x300], data);
+        assert!(buf[0x300..].iter().all(|b| *b == 0));
+
+        let mut buf = [0; 0x200];
+        section.open_decrypted()?.read_at(0x200, &mut buf)?;
+        assert_eq!(buf[..], plaintext);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ivfc_block_size_out_of_range() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        for (block_size_log2, valid) in [(9u32, true), (20, true), (21, false), (64, false)] {
+            let mut nca = build_nca(&[0; 0x200]);
+            let fs_hdr = &mut nca[0x400..0x600];
+            fs_hdr[3] = HashType::HierarchicalIntegrityHash as u8;
+            let hash_data = &mut fs_hdr[8..];
+            hash_data[..4].copy_from_slice(b"IVFC");
+            for (idx, value) in [0x20000u32, 0x20, 2].iter().enumerate() {
+                hash_data[4 + idx * 4..][..4].copy_from_slice(&value.to_le_bytes());
+            }
+            hash_data[0x18..0x20].copy_from_slice(&0x200u64.to_le_bytes());
+            hash_data[0x20..0x24].copy_from_slice(&block_size_log2.to_le_bytes());
+            let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+            nca[0x280..0x2a0].copy_from_slice(&hash);
+
+            let nca = VecStorage::new(nca).map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+            let meta_info = nca
+                .section(0)
+                .expect("section 0 is active")
+                .header()
+                .integrity_meta_info();
+            match valid {
+                true => assert_eq!(meta_info?.data_level().block_size(), 1 << block_size_log2),
+                false => assert!(matches!(
+                    meta_info,
+                    Err(SwonchError::Nca(NcaError::HashDataCorrupted))
+                )),
+            }
+        }
+
+        Ok(())
+    }
+
+    // an AES-CTR section whose metadata hash data covers a table at 0x400 with a single IVFC level
+    fn build_nca_with_metadata(table: &[u8; 0x200], metadata_hash: Option<[u8; 0x20]>) -> Vec<u8> {
+        let mut hash_data = vec![];
+        hash_data.extend(0x400u64.to_le_bytes());
+        hash_data.extend(b"IVFC");
+        for value in [0x20000u32, 0x20, 2] {
+            hash_data.extend(value.to_le_bytes());
+        }
+        hash_data.extend(0u64.to_le_bytes());
+        hash_data.extend(0x200u64.to_le_bytes());
+        hash_data.extend(9u32.to_le_bytes());
+        hash_data.resize(0xc8, 0);
+        hash_data.extend(sha2::Sha256::digest(table));
+        assert_eq!(hash_data.len(), 0xe8);
+
+        let mut section = vec![0; 0x800];
+        section[0x400..0x600].copy_from_slice(table);
+        section[0x600..][..hash_data.len()].copy_from_slice(&hash_data);
+
+        let mut nca = build_nca(&section);
+        let fs_hdr = &mut nca[0x400..0x600];
+        fs_hdr[5] = MetaDataHashType::HierarchicalIntegrity as u8;
+        let info = &mut fs_hdr[0x1a0..0x1d0];
+        info[..8].copy_from_slice(&0x600u64.to_le_bytes());
+        info[8..0x10].copy_from_slice(&(hash_data.len() as u64).to_le_bytes());
+        info[0x10..].copy_from_slice(
+            &metadata_hash.unwrap_or_else(|| sha2::Sha256::digest(&hash_data).into()),
+        );
+        let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+        nca[0x280..0x2a0].copy_from_slice(&hash);
+        nca
+    }
+
+    #[test]
+    fn verify_metadata() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        let table: [u8; 0x200] = core::array::from_fn(|i| (i * 11) as u8);
+        let open = |nca: Vec<u8>| -> SwonchResult<Storage> {
+            let nca = VecStorage::new(nca).map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+            nca.section(0).expect("section 0 is active").open_fs_data()
+        };
+
+        let mut buf = [0; 0x200];
+        open(build_nca_with_metadata(&table, None))?.read_at(0x400, &mut buf)?;
+        assert_eq!(buf, table);
+
+        assert!(matches!(
+            open(build_nca_with_metadata(&table, Some([0; 0x20]))),
+            Err(SwonchError::Nca(NcaError::MetaDataHashMismatch { .. }))
+        ));
+
+        // the hash data is intact, but the table it covers isn't
+        let mut nca = build_nca_with_metadata(&table, None);
+        nca[0xc00 + 0x4f0] ^= 1;
+        assert!(matches!(
+            open(nca),
+            Err(SwonchError::Integrity(
+                IntegrityError::BlockHashMismatch { .. }
+            ))
+        ));
+
+        Ok(())
+    }
+
+    // a throwaway RSA-2048 key and a PSS signature over the header of the NCA in `verify_signatures`
+    const FIXED_KEY_MODULUS: &str = concat!(
+        "bad7bec0628a8d49582577486730f774c2d9e3628d5aed8f0f30e90e2045d583",
+        "17ab4ed682b560ff3c77cfe3e0b27d492ed2a36e240fe0c902bba884b403c81b",
+        "3523adf2f65c0287c07f7b9d30407fd884978efe62387af10d3b9481efc9c3dc",
+        "8ead5998e10728c106a5fae58185962cf7677ea644a20a514ec2fa489e2ccf6a",
+        "c75f697a6c54013c7e7c5650055f24b80d06bcd5ee891f539237302b33fc1e51",
+        "1d22e4701dab264ef2b8cbe97fb649b423984c713419847070c7499a17e81f6f",
+        "ec7c5c4d6aff71b84f65ee7f7daa858b372f0a3d9910fef1760682bb2dace872",
+        "f5d28e93059f52306c5eab8bde15bd1475ae0bc5fa581188497041f1e00671ab",
+    );
+    const FIXED_KEY_SIGNATURE: &str = concat!(
+        "7877b22daaeee001fd4d25d2e40b7902850b42460bee7c0b148c5e349c95ea61",
+        "165ec8ebe2f936f68199a9977fc06c99b4dd44c287fb98f46350668766cc29d5",
+        "158b5b4fb917d0e7893d78a4e9362cf3c80fafdec643a7c483ef0c92785b5aca",
+        "7d58ce4379ed8e4073f7d63453829aa4d3a4c7c83eb47b438a05e529e5db882f",
+        "e528c89ff0e33ce7b47814362305997161e9a077a58881801672662cce1ae928",
+        "3dcc4efcda1f0af2d6aee45b1d72d235decff4e60dce2068bbad9aa30462a11e",
+        "79a08cfce4543759e1ebbc809312dba12d60773de5d753b771a07550411f5f06",
+        "1d31033770a309cd268ac502e9934e486e48b65e03ddc3033102f74d7ee83160",
+    );
+
+    #[test]
+    fn verify_metadata_once() -> SwonchResult<()> {
+        KEYS.insert_key("key_area_key_application", KEY_AREA_KEY, Some(0));
+
+        let table = [0x5a; 0x200];
+        let storage = VecStorage::new_mut(build_nca_with_metadata(&table, None));
+        let nca = storage
+            .clone()
+            .map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+        nca.open_file(b"section0/pfs0")?;
+
+        // corrupting the hash data afterwards goes unnoticed, every section shares the result
+        storage.write_at(0xc00 + 0x600, &[0xff])?;
+        nca.open_file(b"section0/pfs0")?;
+        nca.section(0)
+            .expect("section 0 is active")
+            .open_fs_data()?;
+        assert!(matches!(
+            nca.section(0)
+                .expect("section 0 is active")
+                .verify_metadata(),
+            Err(SwonchError::Nca(NcaError::MetaDataHashMismatch { .. }))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_signatures() -> SwonchResult<()> {
+        let modulus = utils::hex_str_to_vec(FIXED_KEY_MODULUS).expect("invalid hex");
+        KEYS.insert_key("nca_hdr_fixed_key_modulus", &modulus[..], Some(0));
+
+        // not a Program NCA, so only the fixed key signature is checked
+        let mut nca = build_nca(&[0; 0x200]);
+        nca[0x205] = ContentType::Data as u8;
+        nca[..0x100]
+            .copy_from_slice(&utils::hex_str_to_vec(FIXED_KEY_SIGNATURE).expect("invalid hex"));
+        let open =
+            |nca: &[u8], integrity| VecStorage::new(nca.to_vec()).map_to_storage::<Nca>(integrity);
+
+        open(&nca, Integrity::ErrorOnMismatch)?.verify_signatures()?;
+
+        // a different program id
+        nca[0x210] ^= 1;
+        assert!(matches!(
+            open(&nca, Integrity::ErrorOnMismatch)?.verify_signatures(),
+            Err(SwonchError::Nca(NcaError::FixedKeySignatureInvalid))
+        ));
+        open(&nca, Integrity::WarnOnly)?.verify_signatures()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn plaintext_fs_header_check() {
+        let mut fs_header = [0; 0x200];
+        fs_header[..2].copy_from_slice(&2u16.to_le_bytes());
+        assert!(is_plaintext_fs_header(&fs_header));
+
+        // a hashed PartitionFS, which comparing a u32 version would have mistaken for encrypted
+        fs_header[2] = FsType::PartitionFS as u8;
+        fs_header[3] = HashType::HierarchicalSha256Hash as u8;
+        assert!(is_plaintext_fs_header(&fs_header));
+
+        fs_header[..2].copy_from_slice(&[0x8b, 0x3f]);
+        assert!(!is_plaintext_fs_header(&fs_header));
+    }
+
+    const NCA0_XTS_KEY: [u8; 0x20] = *b"nca0 section crypt + tweak keys!";
+
+    // NCA0 with an RSA-OAEP key area and a single AES-XTS section at 0x600, whose fs header is
+    // stored at the start of the section
+    fn build_nca0(data: &[u8; 0x200]) -> Vec<u8> {
+        let mut nca = vec![0; 0x600];
+
+        let hdr = &mut nca[0x200..0x400];
+        hdr[..4].copy_from_slice(b"NCA0");
+        hdr[0x40..0x44].copy_from_slice(&3u32.to_le_bytes());
+        hdr[0x44..0x48].copy_from_slice(&6u32.to_le_bytes());
+        hdr[0x48] = 1;
+
+        // the mask seed and label hash are fixed, followed by the padding and the keys
+        let mut em = [0; 0x100];
+        let (seed, db) = em[1..].split_at_mut(0x20);
+        seed.fill(0x5a);
+        db[..0x20].copy_from_slice(&sha2::Sha256::digest(b""));
+        db[0xbe] = 1;
+        db[0xbf..].copy_from_slice(&NCA0_XTS_KEY);
+        utils::mgf1_xor(db, seed);
+        utils::mgf1_xor(seed, db);
+        hdr[0x100..].copy_from_slice(&em);
+
+        let mut section = vec![0; 0x600];
+        let fs_hdr = &mut section[..0x200];
+        fs_hdr[..2].copy_from_slice(&2u16.to_le_bytes());
+        fs_hdr[2] = FsType::RomFS as u8;
+        fs_hdr[3] = HashType::HierarchicalSha256Hash as u8;
+        fs_hdr[4] = EncryptionType::AesXts as u8;
+
+        // a hash table at 0x200 with the data behind it
+        let hash_table = sha2::Sha256::digest(data);
+        let hash_data = &mut fs_hdr[8..];
+        hash_data[..0x20].copy_from_slice(&sha2::Sha256::digest(hash_table));
+        hash_data[0x20..0x24].copy_from_slice(&0x200u32.to_le_bytes());
+        hash_data[0x24..0x28].copy_from_slice(&2u32.to_le_bytes());
+        for (idx, (offset, size)) in [(0x200u64, 0x20u64), (0x400, 0x200)].iter().enumerate() {
+            let region = &mut hash_data[0x28 + idx * 0x10..];
+            region[..8].copy_from_slice(&offset.to_le_bytes());
+            region[8..0x10].copy_from_slice(&size.to_le_bytes());
+        }
+        nca[0x280..0x2a0].copy_from_slice(&sha2::Sha256::digest(&section[..0x200]));
+
+        section[0x200..0x220].copy_from_slice(&hash_table);
+        section[0x400..].copy_from_slice(data);
+
+        // sectors are counted from the end of the NCA header
+        Into::<Xts128<_>>::into(crate::keyset::Aes128XtsKey(NCA0_XTS_KEY)).encrypt_area(
+            &mut section,
+            0x200,
+            1,
+            utils::aes_xtsn_tweak,
+        );
+
+        nca.extend(section);
+        nca
+    }
+
+    #[test]
+    fn open_nca0() -> SwonchResult<()> {
+        // an exponent of 1 turns RSA into the identity, so the key area only has to be OAEP encoded
+        let mut exponent = [0; 0x100];
+        exponent[0xff] = 1;
+        KEYS.insert_key("beta_nca0_modulus", [0xff; 0x100], None);
+        KEYS.insert_key("beta_nca0_exponent", exponent, None);
+        KEYS.insert_key("beta_nca0_label_hash", &sha2::Sha256::digest(b"")[..], None);
+
+        let data: [u8; 0x200] = core::array::from_fn(|i| (i * 3) as u8);
+        let nca =
+            VecStorage::new(build_nca0(&data)).map_to_storage::<Nca>(Integrity::ErrorOnMismatch)?;
+        assert_eq!(nca.header().decrypt_xts_key()?.0, NCA0_XTS_KEY);
+
+        let section = nca.section(0).expect("section 0 is active");
+        assert_eq!(section.header().fs_type, FsType::RomFS);
+
+        let mut buf = [0; 0x200];
+        section.open_verified_fs_data()?.read_at(0, &mut buf)?;
+        assert_eq!(buf, data);
+
+        let mut buf = [0; 0x10];
+        nca.open_file(b"section0/romfs")?.read_at(0x1f0, &mut buf)?;
+        assert_eq!(buf, data[0x1f0..]);
+
+        Ok(())
+    }
+}
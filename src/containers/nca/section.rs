@@ -1,11 +1,28 @@
 use aes::Aes128;
 use alloc::sync::Arc;
-use binrw::{io::Cursor, BinWrite};
-use sha2::Sha256;
+use binrw::{io::Cursor, BinRead, BinWrite};
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
 
-use crate::{keyset::KEYS, prelude::*, utils::HexArray, SwonchResult};
+use crate::{
+    keyset::KEYS,
+    prelude::*,
+    storage::{
+        bucket_tree::{BucketTree, BucketTreeInfo},
+        crypto::aes_raw::ctr::nca_ctr_iv,
+        integrity::{
+            HashDigest, HierarchicalSha256Storage, IntegrityError, IntegrityVerificationStorage,
+        },
+        CompressedStorage, IndirectStorage, RegionSwitchStorage, SparseStorage,
+    },
+    utils::HexArray,
+    Integrity, SwonchResult,
+};
 
-use super::{Nca, NcaHeader};
+use super::{
+    HashInfo, HierarchicalSha256Data, IntegrityMetaInfo, MetaDataHashData, MetaDataHashInfo,
+    MetaDataHashType, Nca, NcaError, NcaHeader, NcaMagic, Region,
+};
 
 #[binrw::binrw]
 #[brw(little, repr(u8))]
@@ -44,6 +61,40 @@ pub enum HashType {
     HierarchicalIntegritySha3Hash = 6, // 14.0.0+
 }
 
+/// The tables of a patch section, applied on top of the same section of the base NCA.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct PatchInfo {
+    /// Relocation table choosing between base and patch data.
+    pub indirect: BucketTreeInfo,
+    /// Counter generations of the patch data.
+    pub aes_ctr_ex: BucketTreeInfo,
+}
+
+/// The table of a sparse section, mapping the populated parts of the section to the data stored in the NCA.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct SparseInfo {
+    /// Relative to [`SparseInfo::physical_offset`].
+    pub bucket: BucketTreeInfo,
+    /// Offset of the stored data within the NCA.
+    pub physical_offset: u64,
+    pub generation: u16,
+    reserved: [u8; 6],
+}
+
+/// The compression table of a section, stored behind the compressed filesystem data.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionInfo {
+    /// Relative to the start of the filesystem data.
+    pub bucket: BucketTreeInfo,
+    reserved: u64,
+}
+
 #[binrw::binrw]
 #[brw(little)]
 #[derive(Debug, Clone)]
@@ -53,7 +104,7 @@ pub struct FsHeader {
     pub fs_type: FsType,
     pub hash_type: HashType,
     pub encryption_type: EncryptionType,
-    metadata_hash_type: u8,
+    pub metadata_hash_type: MetaDataHashType, // 14.0.0+
     reserved0: HexArray<2>,
     hash_data: HexArray<0xf8>,
     patch_info: HexArray<0x40>,
@@ -72,6 +123,110 @@ impl FsHeader {
 
         crate::utils::validate_hash::<Sha256>(&hdr_buf, hash).map_err(Into::into)
     }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn secure_value(&self) -> u32 {
+        self.secure_value
+    }
+
+    /// Initial AES-CTR counter for a section starting at `section_offset` within the NCA.
+    pub(crate) fn aes_ctr_iv(&self, section_offset: u64) -> [u8; 0x10] {
+        nca_ctr_iv(self.secure_value, self.generation, section_offset)
+    }
+
+    /// Locations of the relocation and AES-CTR-EX tables, `None` if this isn't a patch section.
+    pub fn patch_info(&self) -> SwonchResult<Option<PatchInfo>> {
+        use EncryptionType::*;
+        if !matches!(self.encryption_type, AesCtrEx | AesCtrExSkipLayerHash) {
+            return Ok(Option::None);
+        }
+
+        Ok(Some(PatchInfo::read(&mut Cursor::new(&self.patch_info.0))?))
+    }
+
+    /// The hash data of the section, depending on its [`HashType`].
+    pub fn hash_info(&self) -> SwonchResult<HashInfo> {
+        use HashType::*;
+        Ok(match self.hash_type {
+            None => HashInfo::None,
+            HierarchicalSha256Hash | HierarchicalSha3256Hash => {
+                HashInfo::HierarchicalSha256(self.hierarchical_sha256_data()?)
+            }
+            HierarchicalIntegrityHash | HierarchicalIntegritySha3Hash => {
+                HashInfo::HierarchicalIntegrity(self.integrity_meta_info()?)
+            }
+            Auto | AutoSha3 => return Err(NcaError::UnsupportedHashType(self.hash_type).into()),
+        })
+    }
+
+    /// The sparse table of the section, `None` if the section isn't sparse.
+    pub fn sparse_info(&self) -> SwonchResult<Option<SparseInfo>> {
+        // the generation is never 0 for sparse sections
+        if self.sparse_info.0[0x28..0x2a] == [0, 0] {
+            return Ok(None);
+        }
+
+        Ok(Some(SparseInfo::read(&mut Cursor::new(
+            &self.sparse_info.0,
+        ))?))
+    }
+
+    /// The compression table of the section, `None` if the section isn't compressed.
+    pub fn compression_info(&self) -> SwonchResult<Option<CompressionInfo>> {
+        if &self.compression_info.0[0x10..0x14] != b"BKTR" {
+            return Ok(None);
+        }
+
+        Ok(Some(CompressionInfo::read(&mut Cursor::new(
+            &self.compression_info.0,
+        ))?))
+    }
+
+    /// Where the hash data of the patch and compression tables is, `None` if they aren't hashed.
+    pub fn metadata_hash_info(&self) -> SwonchResult<Option<MetaDataHashInfo>> {
+        Ok(match self.metadata_hash_type {
+            MetaDataHashType::None => None,
+            MetaDataHashType::HierarchicalIntegrity => Some(MetaDataHashInfo::read(
+                &mut Cursor::new(&self.metadata_hash_info.0),
+            )?),
+        })
+    }
+
+    /// The hash data of [`HashType::HierarchicalSha256Hash`] sections.
+    pub fn hierarchical_sha256_data(&self) -> SwonchResult<HierarchicalSha256Data> {
+        use HashType::*;
+        match self.hash_type {
+            HierarchicalSha256Hash | HierarchicalSha3256Hash => {
+                HierarchicalSha256Data::read(&mut Cursor::new(&self.hash_data.0))
+                    .map_err(|_| NcaError::HashDataCorrupted.into())
+            }
+            _ => Err(NcaError::UnsupportedHashType(self.hash_type).into()),
+        }
+    }
+
+    /// The IVFC header of [`HashType::HierarchicalIntegrityHash`] sections.
+    pub fn integrity_meta_info(&self) -> SwonchResult<IntegrityMetaInfo> {
+        use HashType::*;
+        match self.hash_type {
+            HierarchicalIntegrityHash | HierarchicalIntegritySha3Hash => {
+                IntegrityMetaInfo::read(&mut Cursor::new(&self.hash_data.0))
+                    .map_err(|_| NcaError::HashDataCorrupted.into())
+            }
+            _ => Err(NcaError::UnsupportedHashType(self.hash_type).into()),
+        }
+    }
+
+    /// Where the actual filesystem data is inside the section, behind the hash layers.
+    pub fn data_region(&self) -> SwonchResult<Option<Region>> {
+        Ok(match self.hash_info()? {
+            HashInfo::None => None,
+            HashInfo::HierarchicalSha256(hash_data) => Some(hash_data.data_region()),
+            HashInfo::HierarchicalIntegrity(meta_info) => Some(meta_info.data_level().region()),
+        })
+    }
 }
 
 pub struct NcaSection {
@@ -82,6 +237,9 @@ pub struct NcaSection {
     pub(crate) parent_hdr: Arc<NcaHeader>,
     pub(crate) fs_header: Arc<FsHeader>,
     pub(crate) index: u32,
+    pub(crate) integrity: Integrity,
+    /// Shared between every [`NcaSection`] of the same section handed out by the [`Nca`].
+    pub(crate) metadata_verified: Arc<spin::Once>,
 }
 
 impl NcaSection {
@@ -93,15 +251,277 @@ impl NcaSection {
         self.index
     }
 
+    /// Offset of the section from the start of the NCA.
+    pub fn offset(&self) -> u64 {
+        self.parent_hdr.fs_entries[self.index as usize].start_offset_block as u64 * 0x200
+    }
+
     pub fn open_encrypted(&self) -> SwonchResult<Storage> {
+        if let Some(sparse_info) = self.fs_header.sparse_info()? {
+            return self.open_sparse(&sparse_info);
+        }
+
         let fs_entry = &self.parent_hdr.fs_entries[self.index as usize];
 
         self.parent.clone().split(
-            fs_entry.start_offset_block as u64 * 0x200,
+            self.offset(),
             (fs_entry.end_offset_block - fs_entry.start_offset_block) as u64 * 0x200,
         )
     }
 
+    /// Sparse sections only store the populated parts of the section, with the sparse table
+    /// right behind them. The table has its own counter, based on the sparse generation.
+    fn open_sparse(&self, info: &SparseInfo) -> SwonchResult<Storage> {
+        let data = self
+            .parent
+            .clone()
+            .split(info.physical_offset, info.bucket.offset + info.bucket.size)?;
+
+        let iv = nca_ctr_iv(
+            self.fs_header.secure_value,
+            (info.generation as u32) << 16,
+            info.physical_offset,
+        );
+        let tables = self.open_aes_ctr_with_iv(data.clone(), iv)?;
+        let table = BucketTree::read(
+            &tables.split(info.bucket.offset, info.bucket.size)?,
+            &info.bucket.header,
+        )?;
+
+        Ok(SparseStorage::new(data, table).into_storage())
+    }
+
+    /// Name of the filesystem data of this section when mounted, e.g. in a [`FileSystem`](crate::containers::FileSystem).
+    pub fn fs_data_name(&self) -> &'static str {
+        match self.fs_header.fs_type {
+            FsType::RomFS => "romfs",
+            FsType::PartitionFS => "pfs0",
+        }
+    }
+
+    /// Opens the decrypted filesystem data of the section, skipping the hash layers in front of it.
+    pub fn open_fs_data(&self) -> SwonchResult<Storage> {
+        self.verify_metadata_once()?;
+        self.decompressed(self.fs_data(self.decrypted()?)?)
+    }
+
+    /// Like [`NcaSection::open_fs_data`], but every block is checked against the hash layers when
+    /// it is read, according to the [`Integrity`] the NCA was opened with.
+    pub fn open_verified_fs_data(&self) -> SwonchResult<Storage> {
+        self.verify_metadata_once()?;
+        self.decompressed(self.verified_fs_data(self.decrypted()?)?)
+    }
+
+    /// Applies this patch section on top of the same section of the base NCA, returning the
+    /// decrypted section as it looks after the update.
+    pub fn open_patched(&self, base: &NcaSection) -> SwonchResult<Storage> {
+        if !self.is_patch() {
+            return Err(NcaError::NotAPatchSection { index: self.index }.into());
+        }
+
+        self.verify_metadata_once()?;
+        let patch = self.decrypted()?;
+
+        let info = self
+            .fs_header
+            .patch_info()?
+            .ok_or(NcaError::NotAPatchSection { index: self.index })?;
+        let tables = self.open_aes_ctr(self.open_encrypted()?)?;
+        let table = BucketTree::read(
+            &tables.split(info.indirect.offset, info.indirect.size)?,
+            &info.indirect.header,
+        )?;
+
+        Ok(IndirectStorage::new([base.open_decrypted()?, patch], table).into_storage())
+    }
+
+    /// Like [`NcaSection::open_fs_data`], but for the section patched by [`NcaSection::open_patched`].
+    pub fn open_patched_fs_data(&self, base: &NcaSection) -> SwonchResult<Storage> {
+        // the metadata was already verified by open_patched
+        self.decompressed(self.fs_data(self.open_patched(base)?)?)
+    }
+
+    /// Whether this section contains the patch data of an update.
+    pub fn is_patch(&self) -> bool {
+        use EncryptionType::*;
+        matches!(
+            self.fs_header.encryption_type,
+            AesCtrEx | AesCtrExSkipLayerHash
+        )
+    }
+
+    /// Checks the hash data of the patch and compression tables against the hash in the
+    /// [`FsHeader`], then verifies the tables themselves with it. This happens the first time the
+    /// section is opened with the tables in use. Sparse tables are stored outside of the section
+    /// and aren't covered.
+    pub fn verify_metadata(&self) -> SwonchResult<()> {
+        let Some(info) = self.fs_header.metadata_hash_info()? else {
+            return Ok(());
+        };
+
+        // the tables of patch sections use the regular section counter
+        let section = match self.is_patch() {
+            true => self.open_aes_ctr(self.open_encrypted()?)?,
+            false => self.decrypted()?,
+        };
+
+        let mut hasher = Sha256::new();
+        read_chunks(&section, info.offset, info.size, |chunk| {
+            hasher.update(chunk)
+        })?;
+        let hash = hasher.finalize();
+        if hash[..] != info.hash.0 {
+            match self.integrity {
+                Integrity::WarnOnly => log::error!(
+                    "metadata hash data of section {} hash mismatch. FsHeader claims {:?} but actual hash is {}",
+                    self.index,
+                    info.hash,
+                    HexArray::<0x20>(hash.into()),
+                ),
+                Integrity::ErrorOnMismatch => {
+                    return Err(NcaError::MetaDataHashMismatch {
+                        hash_in_header: info.hash.0,
+                        actual_hash: hash.into(),
+                        index: self.index as _,
+                    }
+                    .into())
+                }
+            }
+        }
+
+        let hash_data = MetaDataHashData::read(
+            &mut section.clone().split(info.offset, info.size)?.into_stdio(),
+        )
+        .map_err(|_| NcaError::HashDataCorrupted)?;
+        let layers = section.clone().split(
+            hash_data.layer_info_offset,
+            section
+                .length()?
+                .saturating_sub(hash_data.layer_info_offset),
+        )?;
+        let meta_info = &hash_data.integrity_meta_info;
+        let tables = match self.fs_header.hash_type {
+            HashType::HierarchicalSha3256Hash | HashType::HierarchicalIntegritySha3Hash => {
+                self.ivfc_levels::<Sha3_256>(layers, meta_info)?
+            }
+            _ => self.ivfc_levels::<Sha256>(layers, meta_info)?,
+        };
+
+        // reading the tables once verifies every block of them
+        read_chunks(&tables, 0, tables.length()?, |_| ())
+    }
+
+    fn verified_fs_data(&self, decrypted: Storage) -> SwonchResult<Storage> {
+        use HashType::*;
+        match self.fs_header.hash_type {
+            None => Ok(decrypted),
+            HierarchicalSha256Hash => self.verified_sha256_layers::<Sha256>(decrypted),
+            HierarchicalSha3256Hash => self.verified_sha256_layers::<Sha3_256>(decrypted),
+            HierarchicalIntegrityHash => self.verified_ivfc_levels::<Sha256>(decrypted),
+            HierarchicalIntegritySha3Hash => self.verified_ivfc_levels::<Sha3_256>(decrypted),
+            Auto | AutoSha3 => Err(NcaError::UnsupportedHashType(self.fs_header.hash_type).into()),
+        }
+    }
+
+    fn verified_sha256_layers<D: HashDigest>(&self, decrypted: Storage) -> SwonchResult<Storage> {
+        let hash_data = self.fs_header.hierarchical_sha256_data()?;
+        let layers = hash_data.layers();
+
+        let section_len = decrypted.length()?;
+        let in_section = |layer: &Region| {
+            layer
+                .offset
+                .checked_add(layer.size)
+                .is_some_and(|end| end <= section_len)
+        };
+        if !layers.iter().all(in_section) {
+            return Err(NcaError::HashDataCorrupted.into());
+        }
+
+        // the first layer is small enough to be checked against the master hash in one go
+        let mut layer = decrypted.clone().split(layers[0].offset, layers[0].size)?;
+        let mut buf = vec![0; layers[0].size as usize];
+        layer.read_at(0, &mut buf)?;
+        if let Err(hash) = crate::utils::validate_hash::<D>(&buf, &hash_data.master_hash.0) {
+            match self.integrity {
+                Integrity::WarnOnly => log::error!(
+                    "master hash mismatch on section {}. FsHeader claims {:?} but actual hash is {}",
+                    self.index,
+                    hash_data.master_hash,
+                    HexArray::<0x20>(hash.into()),
+                ),
+                Integrity::ErrorOnMismatch => {
+                    return Err(IntegrityError::MasterHashMismatch {
+                        hash_in_header: hash_data.master_hash.0,
+                        actual_hash: hash.into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        for region in &layers[1..] {
+            let data = decrypted.clone().split(region.offset, region.size)?;
+            layer = HierarchicalSha256Storage::<D>::new(
+                layer,
+                data,
+                hash_data.block_size as u64,
+                self.integrity,
+            )
+            .into_storage();
+        }
+
+        Ok(layer)
+    }
+
+    fn verified_ivfc_levels<D: HashDigest>(&self, decrypted: Storage) -> SwonchResult<Storage> {
+        self.ivfc_levels::<D>(decrypted, &self.fs_header.integrity_meta_info()?)
+    }
+
+    fn ivfc_levels<D: HashDigest>(
+        &self,
+        decrypted: Storage,
+        meta_info: &IntegrityMetaInfo,
+    ) -> SwonchResult<Storage> {
+        // the master hash is the hash table of the first level
+        let mut level = VecStorage::new(meta_info.master_hash.0.into());
+        for info in meta_info.levels() {
+            let data = decrypted.clone().split(info.offset, info.size)?;
+            level = IntegrityVerificationStorage::<D>::new(
+                level,
+                data,
+                info.block_size(),
+                self.integrity,
+            )?
+            .into_storage();
+        }
+
+        Ok(level)
+    }
+
+    /// Compressed sections have the compression table behind the compressed data.
+    fn decompressed(&self, fs_data: Storage) -> SwonchResult<Storage> {
+        let Some(info) = self.fs_header.compression_info()? else {
+            return Ok(fs_data);
+        };
+
+        let table = BucketTree::read(
+            &fs_data
+                .clone()
+                .split(info.bucket.offset, info.bucket.size)?,
+            &info.bucket.header,
+        )?;
+
+        Ok(CompressedStorage::new(fs_data.split(0, info.bucket.offset)?, table).into_storage())
+    }
+
+    fn fs_data(&self, decrypted: Storage) -> SwonchResult<Storage> {
+        match self.fs_header.data_region()? {
+            Some(region) => decrypted.split(region.offset, region.size),
+            None => Ok(decrypted),
+        }
+    }
+
     fn get_key_for_tkey_crypto(&self) -> SwonchResult<[u8; 0x10]> {
         let rights_id = self.parent_hdr.rights_id;
         let tkey_enc = KEYS.get_titlekey(rights_id)?;
@@ -111,51 +531,143 @@ impl NcaSection {
         Ok(tkey)
     }
 
-    pub(crate) fn get_key_for_section_decryption(&self) -> SwonchResult<Option<[u8; 0x10]>> {
-        log::debug!("{:?}", self.fs_header.encryption_type);
-        if self.fs_header.encryption_type == EncryptionType::None {
-            return Ok(None);
-        }
-
+    /// The key for AES-CTR based sections.
+    pub(crate) fn get_key_for_section_decryption(&self) -> SwonchResult<[u8; 0x10]> {
         let rights_id = self.parent_hdr.rights_id;
-        let key_generation = self.parent_hdr.get_key_generation_index();
 
-        Some(if rights_id.0 == 0 {
-            // default crypto
-            todo!()
+        if rights_id.0 == 0 {
+            // standard crypto, the section key for AES-CTR lives in the third key area slot
+            Ok(self.parent_hdr.decrypt_key_area()?[2])
         } else {
             self.get_key_for_tkey_crypto()
-        })
-        .transpose()
+        }
+    }
+
+    /// Runs [`NcaSection::verify_metadata`] unless it already succeeded for this section.
+    fn verify_metadata_once(&self) -> SwonchResult<()> {
+        self.metadata_verified
+            .try_call_once(|| self.verify_metadata())
+            .map(|_| ())
     }
 
+    /// Opens the decrypted section, including the hash layers. The tables of patch sections are
+    /// checked with [`NcaSection::verify_metadata`] first.
     pub fn open_decrypted(&self) -> SwonchResult<Storage> {
-        let rights_id = self.parent_hdr.rights_id;
-        // let key_area_key = KEYS.get_key_index(
-        //    format!(
-        //       "key_area_key_{}",
-        //      self.parent.header.key_area_encryption_key_index
-        // ),
-        // key_generation,
-        //);
+        if self.is_patch() {
+            self.verify_metadata_once()?;
+        }
+        self.decrypted()
+    }
+
+    fn decrypted(&self) -> SwonchResult<Storage> {
         let section_data = self.open_encrypted()?;
-        let key = self.get_key_for_section_decryption()?;
 
         use EncryptionType::*;
         Ok(match self.fs_header.encryption_type {
             None => section_data,
-            Auto => unimplemented!(),
-            AesCtr => {
-                use crate::storage::crypto::AesCtrStorage;
-                use aes::cipher::{KeyInit, KeyIvInit};
-                use ctr::Ctr64LE;
+            AesXts => {
+                use crate::storage::crypto::{aes_raw::xts, AesXtsnStorage};
 
-                let iv = [0u8; 0x10];
-                let aes_ctx = Ctr64LE::<Aes128>::new_from_slices(&key.unwrap(), &iv).unwrap();
+                // sectors are numbered relative to the start of the section, except for NCA0
+                // which counts them from the end of the NCA header
+                let sector_offset = match self.parent_hdr.magic {
+                    NcaMagic::Nca0 => self.offset().saturating_sub(0x400) / 0x200,
+                    _ => 0,
+                };
+                let key = self.parent_hdr.decrypt_xts_key()?;
+                let xts = xts::AesXtsnStorage::new(section_data, key.into(), sector_offset as i64);
 
-                AesCtrStorage::new(section_data, aes_ctx).into_storage()
+                AesXtsnStorage::new(xts).into_storage()
+            }
+            AesCtr => self.open_aes_ctr(section_data)?,
+            AesCtrEx => self.open_aes_ctr_ex(section_data)?,
+            AesCtrSkipLayerHash => {
+                let decrypted = self.open_aes_ctr(section_data.clone())?;
+                self.skip_layer_hash(section_data, decrypted)?
+            }
+            AesCtrExSkipLayerHash => {
+                let decrypted = self.open_aes_ctr_ex(section_data.clone())?;
+                self.skip_layer_hash(section_data, decrypted)?
+            }
+            Auto => {
+                return Err(
+                    NcaError::UnsupportedEncryptionType(self.fs_header.encryption_type).into(),
+                )
             }
-            _ => todo!(),
         })
     }
+
+    fn open_aes_ctr_ex(&self, section_data: Storage) -> SwonchResult<Storage> {
+        use crate::storage::crypto::AesCtrExStorage;
+
+        // the tables at the end of the section use the regular section counter
+        let info = self
+            .fs_header
+            .patch_info()?
+            .ok_or(NcaError::NotAPatchSection { index: self.index })?;
+        let tables = self.open_aes_ctr(section_data.clone())?;
+        let table = BucketTree::read(
+            &tables.split(info.aes_ctr_ex.offset, info.aes_ctr_ex.size)?,
+            &info.aes_ctr_ex.header,
+        )?;
+
+        Ok(AesCtrExStorage::new(
+            section_data,
+            self.get_key_for_section_decryption()?,
+            self.fs_header.secure_value,
+            self.offset(),
+            table,
+        )
+        .into_storage())
+    }
+
+    /// The SkipLayerHash encryption types leave the hash layers in front of the filesystem data
+    /// unencrypted, only the filesystem data and everything behind it is encrypted.
+    fn skip_layer_hash(&self, raw: Storage, decrypted: Storage) -> SwonchResult<Storage> {
+        let hash_target_offset = self
+            .fs_header
+            .data_region()?
+            .map_or(0, |region| region.offset);
+
+        Ok(RegionSwitchStorage::new(raw, decrypted, 0, hash_target_offset).into_storage())
+    }
+
+    fn open_aes_ctr(&self, section_data: Storage) -> SwonchResult<Storage> {
+        self.open_aes_ctr_with_iv(section_data, self.fs_header.aes_ctr_iv(self.offset()))
+    }
+
+    fn open_aes_ctr_with_iv(&self, section_data: Storage, iv: [u8; 0x10]) -> SwonchResult<Storage> {
+        use crate::storage::crypto::AesCtrStorage;
+        use aes::cipher::KeyIvInit;
+        use ctr::Ctr64BE;
+
+        let key = self.get_key_for_section_decryption()?;
+        let aes_ctx = Ctr64BE::<Aes128>::new(&key.into(), &iv.into());
+
+        Ok(AesCtrStorage::new(section_data, aes_ctx).into_storage())
+    }
+}
+
+/// Reads `size` bytes at `offset` in small chunks, so large tables don't have to be read at once.
+fn read_chunks(
+    storage: &Storage,
+    offset: u64,
+    size: u64,
+    mut f: impl FnMut(&[u8]),
+) -> SwonchResult<()> {
+    let mut buf = [0; 0x4000];
+    let mut pos = 0;
+
+    while pos < size {
+        let len = core::cmp::min(size - pos, buf.len() as u64) as usize;
+        let read = storage.read_at(offset + pos, &mut buf[..len])?;
+        f(&buf[..read as usize]);
+
+        pos += read;
+        if read < len as u64 {
+            break;
+        }
+    }
+
+    Ok(())
 }
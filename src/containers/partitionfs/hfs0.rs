@@ -1 +1,209 @@
+use crate::utils::sealed::Sealed;
+use crate::utils::string_table::StringTable;
+use crate::{storage::IStorage, utils::HexArray, Integrity, SwonchResult};
+use alloc::{string::ToString, vec::Vec};
+use sha2::Sha256;
 
+use super::{CommonHeader, Entry, EntryLike, HeaderLike, PartitionFsError};
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+#[binrw::binrw]
+#[brw(little)]
+pub struct RawHfs0Entry {
+    offset: u64,
+    size: u64,
+    string_offset: u32,
+    hashed_region_size: u32,
+    reserved: u64,
+    hash: HexArray<0x20>,
+}
+
+impl RawHfs0Entry {
+    /// Size of the region at the start of the entry that is covered by [`RawHfs0Entry::hash`].
+    pub fn hashed_region_size(&self) -> u32 {
+        self.hashed_region_size
+    }
+
+    /// SHA-256 over the first [`RawHfs0Entry::hashed_region_size`] bytes of the entry.
+    pub fn hash(&self) -> &[u8; 0x20] {
+        &self.hash.0
+    }
+}
+
+impl Sealed for RawHfs0Entry {}
+impl EntryLike for RawHfs0Entry {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn string_offset(&self) -> u32 {
+        self.string_offset
+    }
+}
+
+#[binrw::binrw]
+#[brw(magic = b"HFS0", little)]
+pub struct Hfs0Header(CommonHeader<RawHfs0Entry>);
+
+impl Hfs0Header {
+    const STATIC_HDR_SIZE: usize = 0x4 + 0x4 + 0x4 + 0x4;
+    const ENTRY_SIZE: usize = 0x40;
+
+    pub fn size(&self) -> usize {
+        Self::STATIC_HDR_SIZE
+            + self.0.entries.len() * Self::ENTRY_SIZE
+            + self.0.string_table.as_bytes().len()
+    }
+}
+
+impl Sealed for Hfs0Header {}
+impl HeaderLike for Hfs0Header {
+    type RawEntry = RawHfs0Entry;
+
+    fn entries(&self) -> &Vec<Self::RawEntry> {
+        &self.0.entries
+    }
+
+    fn size(&self) -> usize {
+        Hfs0Header::size(self)
+    }
+
+    fn string_table(&self) -> &StringTable {
+        &self.0.string_table
+    }
+}
+
+pub type Hfs0 = super::PartitionFs<Hfs0Header>;
+
+impl<'a> Entry<'a, Hfs0Header> {
+    pub fn hash(&self) -> &[u8; 0x20] {
+        self.raw.hash()
+    }
+
+    pub fn hashed_region_size(&self) -> u32 {
+        self.raw.hashed_region_size()
+    }
+
+    /// Hashes the start of the entry and compares it to the hash stored in the header.
+    pub fn verify(&self, integrity: Integrity) -> SwonchResult<()> {
+        let mut buf = vec![0; self.hashed_region_size() as usize];
+        let read = self.data()?.read_at(0, &mut buf)?;
+
+        let hash_is_valid =
+            crate::utils::validate_hash::<Sha256>(&buf[..read as usize], self.hash());
+        if let Err(hash) = hash_is_valid {
+            match integrity {
+                Integrity::WarnOnly => log::error!(
+                    "HFS0 entry {:?} hash mismatch. header claims {:?} but actual hash is {}",
+                    self.name(),
+                    self.raw.hash,
+                    HexArray::<0x20>(hash.into()),
+                ),
+                Integrity::ErrorOnMismatch => {
+                    return Err(PartitionFsError::EntryHashMismatch {
+                        name: self.name().to_string(),
+                        hash_in_header: *self.hash(),
+                        actual_hash: hash.into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Hfs0 {
+    /// Verifies the hashed region of every entry, see [`Entry::verify`].
+    pub fn verify(&self, integrity: Integrity) -> SwonchResult<()> {
+        self.files().try_for_each(|entry| entry.verify(integrity))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{storage::VecStorage, SwonchError};
+    use sha2::Digest;
+
+    /// Builds an HFS0 whose entries hash all of their data.
+    pub(crate) fn build_hfs0(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let string_table: Vec<u8> = entries
+            .iter()
+            .flat_map(|(name, _)| name.bytes().chain([0]))
+            .collect();
+
+        let mut hfs0 = vec![];
+        hfs0.extend(b"HFS0");
+        hfs0.extend((entries.len() as u32).to_le_bytes());
+        hfs0.extend((string_table.len() as u32).to_le_bytes());
+        hfs0.extend(0u32.to_le_bytes());
+
+        let (mut offset, mut string_offset) = (0u64, 0u32);
+        for (name, data) in entries {
+            hfs0.extend(offset.to_le_bytes());
+            hfs0.extend((data.len() as u64).to_le_bytes());
+            hfs0.extend(string_offset.to_le_bytes());
+            hfs0.extend((data.len() as u32).to_le_bytes());
+            hfs0.extend(0u64.to_le_bytes());
+            hfs0.extend(Sha256::digest(data));
+
+            offset += data.len() as u64;
+            string_offset += name.len() as u32 + 1;
+        }
+
+        hfs0.extend(string_table);
+        entries.iter().for_each(|(_, data)| hfs0.extend(*data));
+        hfs0
+    }
+
+    fn open(hfs0: Vec<u8>) -> SwonchResult<Hfs0> {
+        VecStorage::new(hfs0).map_to_storage::<Hfs0>(())
+    }
+
+    fn is_hash_mismatch(result: SwonchResult<()>, entry: &str) -> bool {
+        matches!(
+            result,
+            Err(SwonchError::PartitionFs(PartitionFsError::EntryHashMismatch { name, .. }))
+                if name == entry
+        )
+    }
+
+    #[test]
+    fn verify_entries() -> SwonchResult<()> {
+        let hfs0 = build_hfs0(&[("update", b"update data"), ("secure", b"secure data")]);
+        let valid = open(hfs0.clone())?;
+        for integrity in [Integrity::WarnOnly, Integrity::ErrorOnMismatch] {
+            assert!(valid.verify(integrity).is_ok());
+            assert!(valid.files().all(|entry| entry.verify(integrity).is_ok()));
+        }
+
+        // flip the last byte of the secure entry
+        let mut corrupted = hfs0;
+        *corrupted.last_mut().expect("empty HFS0") ^= 0xff;
+        let corrupted = open(corrupted)?;
+        let secure = corrupted
+            .files()
+            .find(|entry| entry.name() == "secure")
+            .expect("secure entry not found");
+
+        assert!(is_hash_mismatch(
+            secure.verify(Integrity::ErrorOnMismatch),
+            "secure"
+        ));
+        assert!(is_hash_mismatch(
+            corrupted.verify(Integrity::ErrorOnMismatch),
+            "secure"
+        ));
+        assert!(secure.verify(Integrity::WarnOnly).is_ok());
+        assert!(corrupted.verify(Integrity::WarnOnly).is_ok());
+
+        Ok(())
+    }
+}
@@ -2,12 +2,13 @@ pub mod hfs0;
 pub mod pfs0;
 
 use crate::{
+    containers::fs::{self, DirEntry, FileSystem, FsError, Metadata},
     storage::{mapper::FromStorage, IStorage, Storage},
     utils::{sealed::Sealed, string_table::StringTable},
     SwonchResult,
 };
 
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 use binrw::meta::ReadEndian;
 use binrw::{BinRead, BinWrite};
 use bstr::BStr;
@@ -92,6 +93,16 @@ where
     fn string_table(&self) -> &StringTable;
 }
 
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum PartitionFsError {
+    #[error("hash mismatch on the hashed region of entry {name:?}")]
+    EntryHashMismatch {
+        name: String,
+        hash_in_header: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+    },
+}
+
 pub struct PartitionFs<H: BinRead + BinWrite> {
     hdr: H,
     data: Storage,
@@ -118,6 +129,65 @@ impl<H: HeaderLike> PartitionFs<H> {
     }
 }
 
+impl<H: HeaderLike> fmt::Debug for PartitionFs<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PartitionFs")
+            .field("files", &self.files().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H: HeaderLike> PartitionFs<H> {
+    /// Resolves a path to an entry, `None` means the path refers to the root.
+    fn lookup(&self, path: &[u8]) -> SwonchResult<Option<Entry<'_, H>>> {
+        let mut components = fs::path_components(path);
+
+        let Some(name) = components.next() else {
+            return Ok(None);
+        };
+
+        let entry = self
+            .files()
+            .find(|e| e.name() == name)
+            .ok_or_else(|| FsError::not_found(path))?;
+
+        match components.next() {
+            // partition filesystems are flat, there are no subdirectories
+            Some(_) => Err(FsError::not_a_directory(path).into()),
+            None => Ok(Some(entry)),
+        }
+    }
+}
+
+impl<H: HeaderLike> FileSystem for PartitionFs<H> {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+        match self.lookup(path)? {
+            Some(entry) => entry.data(),
+            None => Err(FsError::not_a_file(path).into()),
+        }
+    }
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+        match self.lookup(path)? {
+            Some(_) => Err(FsError::not_a_directory(path).into()),
+            None => Ok(self
+                .files()
+                .map(|e| DirEntry {
+                    name: e.name().into(),
+                    metadata: Metadata::file(e.raw.size()),
+                })
+                .collect()),
+        }
+    }
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+        Ok(match self.lookup(path)? {
+            Some(entry) => Metadata::file(entry.raw.size()),
+            None => Metadata::dir(),
+        })
+    }
+}
+
 impl<H: HeaderLike> FromStorage for PartitionFs<H> {
     type Args = ();
     type Output = SwonchResult<Self>;
@@ -0,0 +1,654 @@
+//! Read-only access to the RomFS filesystem found in NCA sections.
+
+use crate::{
+    containers::fs::{path_components, DirEntry, FileSystem, FsError, Metadata},
+    storage::{FromStorage, IStorage, Storage},
+    SwonchResult,
+};
+use alloc::vec::Vec;
+use binrw::{io::Cursor, BinRead};
+use bstr::{BStr, ByteSlice};
+use core::fmt;
+
+/// Marker for an unused slot in the hash tables and for missing links between entries.
+const EMPTY: u32 = 0xffff_ffff;
+
+/// Size of the fields before the name of a [`RawDirEntry`].
+const DIR_ENTRY_SIZE: usize = 0x18;
+/// Size of the fields before the name of a [`RawFileEntry`].
+const FILE_ENTRY_SIZE: usize = 0x20;
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct RomFsHeader {
+    pub header_size: u64,
+    pub dir_hash_table_offset: u64,
+    pub dir_hash_table_size: u64,
+    pub dir_meta_table_offset: u64,
+    pub dir_meta_table_size: u64,
+    pub file_hash_table_offset: u64,
+    pub file_hash_table_size: u64,
+    pub file_meta_table_offset: u64,
+    pub file_meta_table_size: u64,
+    pub data_offset: u64,
+}
+
+/// The header of NCA0 RomFS, same as [`RomFsHeader`] but with 32 bit offsets and sizes.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+struct Nca0RomFsHeader {
+    header_size: u32,
+    dir_hash_table_offset: u32,
+    dir_hash_table_size: u32,
+    dir_meta_table_offset: u32,
+    dir_meta_table_size: u32,
+    file_hash_table_offset: u32,
+    file_hash_table_size: u32,
+    file_meta_table_offset: u32,
+    file_meta_table_size: u32,
+    data_offset: u32,
+}
+
+impl From<Nca0RomFsHeader> for RomFsHeader {
+    fn from(hdr: Nca0RomFsHeader) -> Self {
+        Self {
+            header_size: hdr.header_size.into(),
+            dir_hash_table_offset: hdr.dir_hash_table_offset.into(),
+            dir_hash_table_size: hdr.dir_hash_table_size.into(),
+            dir_meta_table_offset: hdr.dir_meta_table_offset.into(),
+            dir_meta_table_size: hdr.dir_meta_table_size.into(),
+            file_hash_table_offset: hdr.file_hash_table_offset.into(),
+            file_hash_table_size: hdr.file_hash_table_size.into(),
+            file_meta_table_offset: hdr.file_meta_table_offset.into(),
+            file_meta_table_size: hdr.file_meta_table_size.into(),
+            data_offset: hdr.data_offset.into(),
+        }
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+struct RawDirEntry {
+    parent: u32,
+    sibling: u32,
+    child_dir: u32,
+    child_file: u32,
+    hash_next: u32,
+    name_len: u32,
+    #[br(count = name_len)]
+    name: Vec<u8>,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+struct RawFileEntry {
+    parent: u32,
+    sibling: u32,
+    offset: u64,
+    size: u64,
+    hash_next: u32,
+    name_len: u32,
+    #[br(count = name_len)]
+    name: Vec<u8>,
+}
+
+/// Hash used to index the directory and file hash tables.
+fn path_hash(parent: u32, name: &[u8]) -> u32 {
+    name.iter().fold(parent ^ 123456789, |hash, c| {
+        hash.rotate_right(5) ^ *c as u32
+    })
+}
+
+pub struct RomFs {
+    hdr: RomFsHeader,
+    dir_hash_table: Vec<u32>,
+    dir_meta_table: Vec<u8>,
+    file_hash_table: Vec<u32>,
+    file_meta_table: Vec<u8>,
+    data: Storage,
+}
+
+impl RomFs {
+    pub fn header(&self) -> &RomFsHeader {
+        &self.hdr
+    }
+
+    pub fn root(&self) -> Dir<'_> {
+        self.dir_at(0)
+            .expect("BUG: root directory was validated when opening the RomFS")
+    }
+
+    /// Looks up a file or directory by its path from the root, e.g. `/data/foo.bin`.
+    pub fn lookup(&self, path: impl AsRef<[u8]>) -> Option<RomFsEntry<'_>> {
+        let mut components = path_components(path.as_ref()).peekable();
+        let mut dir = self.root();
+
+        while let Some(name) = components.next() {
+            if components.peek().is_none() {
+                return self
+                    .find_file(dir.offset, name)
+                    .map(RomFsEntry::File)
+                    .or_else(|| self.find_dir(dir.offset, name).map(RomFsEntry::Dir));
+            }
+
+            dir = self.find_dir(dir.offset, name)?;
+        }
+
+        Some(RomFsEntry::Dir(dir))
+    }
+
+    pub fn open_dir(&self, path: impl AsRef<[u8]>) -> Option<Dir<'_>> {
+        match self.lookup(path)? {
+            RomFsEntry::Dir(d) => Some(d),
+            RomFsEntry::File(_) => None,
+        }
+    }
+
+    pub fn open_file(&self, path: impl AsRef<[u8]>) -> Option<File<'_>> {
+        match self.lookup(path)? {
+            RomFsEntry::File(f) => Some(f),
+            RomFsEntry::Dir(_) => None,
+        }
+    }
+
+    fn dir_at(&self, offset: u32) -> Option<Dir<'_>> {
+        let buf = self.dir_meta_table.get(offset as usize..)?;
+        let raw = RawDirEntry::read(&mut Cursor::new(buf)).ok()?;
+        Some(Dir {
+            fs: self,
+            offset,
+            raw,
+        })
+    }
+
+    fn file_at(&self, offset: u32) -> Option<File<'_>> {
+        let buf = self.file_meta_table.get(offset as usize..)?;
+        let raw = RawFileEntry::read(&mut Cursor::new(buf)).ok()?;
+        Some(File {
+            fs: self,
+            offset,
+            raw,
+        })
+    }
+
+    /// Upper bound on the number of directories, used to stop following cyclic links.
+    fn max_dirs(&self) -> usize {
+        self.dir_meta_table.len() / DIR_ENTRY_SIZE
+    }
+
+    /// Upper bound on the number of files, used to stop following cyclic links.
+    fn max_files(&self) -> usize {
+        self.file_meta_table.len() / FILE_ENTRY_SIZE
+    }
+
+    fn find_dir(&self, parent: u32, name: &[u8]) -> Option<Dir<'_>> {
+        let table = &self.dir_hash_table;
+        let bucket = path_hash(parent, name) as usize % table.len().max(1);
+        let mut offset = *table.get(bucket)?;
+
+        // a chain can't be longer than the number of entries, anything more is a cycle
+        for _ in 0..self.max_dirs() {
+            if offset == EMPTY {
+                break;
+            }
+            let dir = self.dir_at(offset)?;
+            if dir.raw.parent == parent && dir.raw.name == name {
+                return Some(dir);
+            }
+            offset = dir.raw.hash_next;
+        }
+
+        None
+    }
+
+    fn find_file(&self, parent: u32, name: &[u8]) -> Option<File<'_>> {
+        let table = &self.file_hash_table;
+        let bucket = path_hash(parent, name) as usize % table.len().max(1);
+        let mut offset = *table.get(bucket)?;
+
+        for _ in 0..self.max_files() {
+            if offset == EMPTY {
+                break;
+            }
+            let file = self.file_at(offset)?;
+            if file.raw.parent == parent && file.raw.name == name {
+                return Some(file);
+            }
+            offset = file.raw.hash_next;
+        }
+
+        None
+    }
+}
+
+impl FileSystem for RomFs {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+        match self.lookup(path) {
+            Some(RomFsEntry::File(f)) => f.data(),
+            Some(RomFsEntry::Dir(_)) => Err(FsError::not_a_file(path).into()),
+            None => Err(FsError::not_found(path).into()),
+        }
+    }
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+        match self.lookup(path) {
+            Some(RomFsEntry::Dir(d)) => Ok(d
+                .entries()
+                .map(|e| DirEntry {
+                    name: e.name().into(),
+                    metadata: e.metadata(),
+                })
+                .collect()),
+            Some(RomFsEntry::File(_)) => Err(FsError::not_a_directory(path).into()),
+            None => Err(FsError::not_found(path).into()),
+        }
+    }
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+        self.lookup(path)
+            .map(|e| e.metadata())
+            .ok_or_else(|| FsError::not_found(path).into())
+    }
+}
+
+impl fmt::Debug for RomFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RomFs")
+            .field("hdr", &self.hdr)
+            .field("data", &self.data)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStorage for RomFs {
+    type Args = ();
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, _: Self::Args) -> Self::Output {
+        // the smaller header of NCA0 RomFS can be told apart by its size
+        let mut header_size = [0; 4];
+        parent.read_at(0, &mut header_size)?;
+        let hdr = match u32::from_le_bytes(header_size) {
+            0x28 => Nca0RomFsHeader::read(&mut parent.clone().into_stdio())?.into(),
+            _ => RomFsHeader::read(&mut parent.clone().into_stdio())?,
+        };
+
+        // the sizes come straight from the header, check them before allocating anything
+        let parent_len = parent.length()?;
+        let read_table = |offset: u64, size: u64, name| -> SwonchResult<Vec<u8>> {
+            if offset.checked_add(size).is_none_or(|end| end > parent_len) {
+                return Err(RomFsError::TableOutOfBounds(name).into());
+            }
+
+            let mut buf = vec![0; size as usize];
+            parent.read_at(offset, &mut buf)?;
+            Ok(buf)
+        };
+
+        let to_hash_table = |raw: Vec<u8>| -> Vec<u32> {
+            raw.into_iter()
+                .array_chunks::<4>()
+                .map(u32::from_le_bytes)
+                .collect()
+        };
+
+        let dir_hash_table = to_hash_table(read_table(
+            hdr.dir_hash_table_offset,
+            hdr.dir_hash_table_size,
+            "directory hash table",
+        )?);
+        let dir_meta_table = read_table(
+            hdr.dir_meta_table_offset,
+            hdr.dir_meta_table_size,
+            "directory metadata table",
+        )?;
+        let file_hash_table = to_hash_table(read_table(
+            hdr.file_hash_table_offset,
+            hdr.file_hash_table_size,
+            "file hash table",
+        )?);
+        let file_meta_table = read_table(
+            hdr.file_meta_table_offset,
+            hdr.file_meta_table_size,
+            "file metadata table",
+        )?;
+
+        let data_len = parent_len.saturating_sub(hdr.data_offset);
+        let data = parent.split(hdr.data_offset, data_len)?;
+
+        let fs = Self {
+            hdr,
+            dir_hash_table,
+            dir_meta_table,
+            file_hash_table,
+            file_meta_table,
+            data,
+        };
+
+        if fs.dir_at(0).is_none() {
+            return Err(RomFsError::MissingRootDirectory.into());
+        }
+
+        Ok(fs)
+    }
+}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum RomFsError {
+    #[error("the directory metadata table does not contain a root directory")]
+    MissingRootDirectory,
+
+    #[error("the {0} is out of bounds")]
+    TableOutOfBounds(&'static str),
+}
+
+/// Either a [`Dir`] or a [`File`] inside of a [`RomFs`].
+#[derive(Debug)]
+pub enum RomFsEntry<'a> {
+    Dir(Dir<'a>),
+    File(File<'a>),
+}
+
+impl<'a> RomFsEntry<'a> {
+    pub fn name(&self) -> &BStr {
+        match self {
+            RomFsEntry::Dir(d) => d.name(),
+            RomFsEntry::File(f) => f.name(),
+        }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        match self {
+            RomFsEntry::Dir(_) => Metadata::dir(),
+            RomFsEntry::File(f) => Metadata::file(f.size()),
+        }
+    }
+}
+
+pub struct Dir<'a> {
+    fs: &'a RomFs,
+    offset: u32,
+    raw: RawDirEntry,
+}
+
+impl<'a> Dir<'a> {
+    /// Name of the directory, empty for the root.
+    pub fn name(&self) -> &BStr {
+        self.raw.name.as_bstr()
+    }
+
+    pub fn parent(&self) -> Option<Dir<'a>> {
+        match self.offset {
+            0 => None,
+            _ => self.fs.dir_at(self.raw.parent),
+        }
+    }
+
+    pub fn dirs(&self) -> impl Iterator<Item = Dir<'a>> {
+        let fs = self.fs;
+        let mut next = self.raw.child_dir;
+
+        core::iter::from_fn(move || {
+            let dir = (next != EMPTY).then(|| fs.dir_at(next)).flatten()?;
+            next = dir.raw.sibling;
+            Some(dir)
+        })
+        .take(fs.max_dirs())
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = File<'a>> {
+        let fs = self.fs;
+        let mut next = self.raw.child_file;
+
+        core::iter::from_fn(move || {
+            let file = (next != EMPTY).then(|| fs.file_at(next)).flatten()?;
+            next = file.raw.sibling;
+            Some(file)
+        })
+        .take(fs.max_files())
+    }
+
+    /// Iterates over the subdirectories followed by the files in this directory.
+    pub fn entries(&self) -> impl Iterator<Item = RomFsEntry<'a>> {
+        self.dirs()
+            .map(RomFsEntry::Dir)
+            .chain(self.files().map(RomFsEntry::File))
+    }
+}
+
+impl<'a> fmt::Debug for Dir<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dir")
+            .field("name", &self.name())
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+pub struct File<'a> {
+    fs: &'a RomFs,
+    offset: u32,
+    raw: RawFileEntry,
+}
+
+impl<'a> File<'a> {
+    pub fn name(&self) -> &BStr {
+        self.raw.name.as_bstr()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.raw.size
+    }
+
+    /// Offset of the file data relative to the RomFS data region.
+    pub fn data_offset(&self) -> u64 {
+        self.raw.offset
+    }
+
+    pub fn parent(&self) -> Option<Dir<'a>> {
+        self.fs.dir_at(self.raw.parent)
+    }
+
+    pub fn data(&self) -> SwonchResult<Storage> {
+        self.fs.data.clone().split(self.raw.offset, self.raw.size)
+    }
+}
+
+impl<'a> fmt::Debug for File<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("File")
+            .field("name", &self.name())
+            .field("offset", &self.offset)
+            .field("data_offset", &self.raw.offset)
+            .field("size", &self.raw.size)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{containers::fs::Walk, storage::VecStorage, SwonchError};
+    use binrw::BinWrite;
+
+    fn entry(fields: &[u32], name: &[u8]) -> Vec<u8> {
+        let mut buf: Vec<u8> = fields.iter().flat_map(|f| f.to_le_bytes()).collect();
+        buf.extend((name.len() as u32).to_le_bytes());
+        buf.extend(name);
+        buf.resize(buf.len().next_multiple_of(4), 0);
+        buf
+    }
+
+    // builds a romfs containing `/foo.bin` and `/data/bar.bin`, with single bucket hash tables.
+    // a header size of 0x28 makes it use the NCA0 header
+    pub(crate) fn build_romfs(header_size: u64) -> SwonchResult<Vec<u8>> {
+        // parent, sibling, child dir, child file, hash next
+        let dir_meta = [
+            entry(&[0, EMPTY, 0x18, 0, 0x18], b""),
+            entry(&[0, EMPTY, EMPTY, 0x28, EMPTY], b"data"),
+        ]
+        .concat();
+
+        // parent, sibling, offset (u64), size (u64), hash next
+        let file_meta = [
+            entry(&[0, EMPTY, 0, 0, 3, 0, 0x28], b"foo.bin"),
+            entry(&[0x18, EMPTY, 3, 0, 4, 0, EMPTY], b"bar.bin"),
+        ]
+        .concat();
+
+        let tables = [
+            0u32.to_le_bytes().into(),
+            dir_meta,
+            0u32.to_le_bytes().into(),
+            file_meta,
+        ];
+        let mut regions = vec![];
+        let mut offset = header_size;
+        for t in &tables {
+            regions.push((offset, t.len() as u64));
+            offset += t.len() as u64;
+        }
+
+        let hdr = RomFsHeader {
+            header_size,
+            dir_hash_table_offset: regions[0].0,
+            dir_hash_table_size: regions[0].1,
+            dir_meta_table_offset: regions[1].0,
+            dir_meta_table_size: regions[1].1,
+            file_hash_table_offset: regions[2].0,
+            file_hash_table_size: regions[2].1,
+            file_meta_table_offset: regions[3].0,
+            file_meta_table_size: regions[3].1,
+            data_offset: offset,
+        };
+
+        let mut out = Cursor::new(Vec::new());
+        hdr.write(&mut out)?;
+        let mut out = out.into_inner();
+        if header_size == 0x28 {
+            out = out
+                .into_iter()
+                .array_chunks::<8>()
+                .flat_map(|field| (u64::from_le_bytes(field) as u32).to_le_bytes())
+                .collect();
+        }
+        tables.iter().for_each(|t| out.extend(t));
+        out.extend(b"foobar!");
+        Ok(out)
+    }
+
+    #[test]
+    fn lookup_and_read() -> SwonchResult<()> {
+        let romfs = VecStorage::new(build_romfs(0x50)?).map_to_storage::<RomFs>(())?;
+
+        let names: Vec<_> = romfs
+            .root()
+            .entries()
+            .map(|e| e.name().to_owned())
+            .collect();
+        assert_eq!(names, [b"data".as_bstr(), b"foo.bin".as_bstr()]);
+
+        let mut buf = [0; 4];
+        let bar = romfs.open_file("/data/bar.bin").expect("bar.bin not found");
+        bar.data()?.read_at(0, &mut buf)?;
+        assert_eq!(&buf, b"bar!");
+        assert_eq!(
+            bar.parent().map(|p| p.name().to_owned()),
+            Some(b"data".into())
+        );
+
+        assert!(romfs.open_dir("data").is_some());
+        assert!(romfs.open_file("/data").is_none());
+        assert!(romfs.lookup("/nope").is_none());
+
+        let paths = romfs
+            .walk()
+            .map(|e| e.map(|(path, _)| path))
+            .collect::<SwonchResult<Vec<_>>>()?;
+        assert_eq!(paths, ["/data", "/data/bar.bin", "/foo.bin"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cyclic_links() -> SwonchResult<()> {
+        let mut raw = build_romfs(0x50)?;
+        let table_offset = |field: usize| {
+            u64::from_le_bytes(raw[field..][..8].try_into().expect("8 bytes")) as usize
+        };
+        let (dir_meta, file_meta) = (table_offset(0x18), table_offset(0x38));
+
+        let mut patch = |offset: usize, value: u32| {
+            raw[offset..][..4].copy_from_slice(&value.to_le_bytes());
+        };
+        // `data` links back to the root in the hash chain and to itself as a sibling
+        patch(dir_meta + 0x18 + 0x10, 0);
+        patch(dir_meta + 0x18 + 4, 0x18);
+        // same for the files, with `bar.bin` linking back to `foo.bin`
+        patch(file_meta + 0x28 + 0x18, 0);
+        patch(file_meta + 4, 0);
+
+        let romfs = VecStorage::new(raw).map_to_storage::<RomFs>(())?;
+        assert!(romfs.lookup("/nope").is_none());
+        assert!(romfs.lookup("/data/nope").is_none());
+        assert!(romfs.open_file("/data/bar.bin").is_some());
+        assert_eq!(romfs.root().dirs().count(), 2);
+        assert_eq!(romfs.root().files().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn walk_cyclic_dirs() -> SwonchResult<()> {
+        let mut raw = build_romfs(0x50)?;
+        let dir_meta = u64::from_le_bytes(raw[0x18..][..8].try_into().expect("8 bytes")) as usize;
+        // the root lists itself as its only subdirectory
+        raw[dir_meta + 8..][..4].copy_from_slice(&0u32.to_le_bytes());
+
+        let romfs = VecStorage::new(raw).map_to_storage::<RomFs>(())?;
+        let entries: Vec<_> = romfs.walk().collect();
+        assert!(entries.len() <= 2 * Walk::MAX_DEPTH);
+        assert!(entries.iter().any(|e| matches!(
+            e,
+            Err(SwonchError::Fs(FsError::TooDeep { path })) if path.len() == Walk::MAX_DEPTH
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_out_of_bounds_tables() -> SwonchResult<()> {
+        // a huge table size isn't allocated, and an offset near the end doesn't overflow
+        for (field, value, table) in [
+            (0x20, u64::MAX, "directory metadata table"),
+            (0x10, 0x10000, "directory hash table"),
+            (0x38, u64::MAX, "file metadata table"),
+        ] {
+            let mut raw = build_romfs(0x50)?;
+            raw[field..][..8].copy_from_slice(&value.to_le_bytes());
+            assert!(matches!(
+                VecStorage::new(raw).map_to_storage::<RomFs>(()),
+                Err(SwonchError::RomFs(RomFsError::TableOutOfBounds(name))) if name == table
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn nca0_header() -> SwonchResult<()> {
+        let romfs = VecStorage::new(build_romfs(0x28)?).map_to_storage::<RomFs>(())?;
+        assert_eq!(romfs.header().header_size, 0x28);
+
+        let mut buf = [0; 4];
+        let bar = romfs.open_file("/data/bar.bin").expect("bar.bin not found");
+        bar.data()?.read_at(0, &mut buf)?;
+        assert_eq!(&buf, b"bar!");
+
+        Ok(())
+    }
+}
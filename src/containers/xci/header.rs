@@ -0,0 +1,233 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, KeyInit},
+    Aes128,
+};
+use binrw::{io::Cursor, BinRead};
+use core::fmt;
+
+use crate::{
+    common::ProgramId,
+    keyset::{Aes128Key, KEYS},
+    utils::HexArray,
+    SwonchResult,
+};
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomSize {
+    #[brw(magic = 0xfau8)]
+    Size1GB,
+    #[brw(magic = 0xf8u8)]
+    Size2GB,
+    #[brw(magic = 0xf0u8)]
+    Size4GB,
+    #[brw(magic = 0xe0u8)]
+    Size8GB,
+    #[brw(magic = 0xe1u8)]
+    Size16GB,
+    #[brw(magic = 0xe2u8)]
+    Size32GB,
+    Unknown(u8),
+}
+
+impl fmt::Display for RomSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomSize::Size1GB => write!(f, "1GB"),
+            RomSize::Size2GB => write!(f, "2GB"),
+            RomSize::Size4GB => write!(f, "4GB"),
+            RomSize::Size8GB => write!(f, "8GB"),
+            RomSize::Size16GB => write!(f, "16GB"),
+            RomSize::Size32GB => write!(f, "32GB"),
+            RomSize::Unknown(v) => write!(f, "Unknown ({v:#x})"),
+        }
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardFlags(pub u8);
+
+impl CardFlags {
+    pub fn auto_boot(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    pub fn history_erase(&self) -> bool {
+        self.0 & 2 != 0
+    }
+
+    pub fn repair_tool(&self) -> bool {
+        self.0 & 4 != 0
+    }
+
+    pub fn different_region_cup_to_terra_device(&self) -> bool {
+        self.0 & 8 != 0
+    }
+
+    pub fn different_region_cup_to_global_device(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct XciHeader {
+    pub header_signature: HexArray<0x100>,
+    #[brw(magic = b"HEAD")]
+    // in units of 0x200 byte media pages
+    pub secure_area_start_page: u32,
+    pub backup_area_start_page: u32,
+    pub title_key_dec_index: u8,
+    pub rom_size: RomSize,
+    pub card_header_version: u8,
+    pub flags: CardFlags,
+    pub package_id: u64,
+    pub valid_data_end_page: u32,
+    pub reserved: u32,
+    pub iv: HexArray<0x10>,
+    pub root_hfs0_offset: u64,
+    pub root_hfs0_header_size: u64,
+    pub root_hfs0_header_hash: HexArray<0x20>,
+    pub initial_data_hash: HexArray<0x20>,
+    pub sel_sec: u32,
+    pub sel_t1_key: u32,
+    pub sel_key: u32,
+    pub lim_area_page: u32,
+    pub encrypted_info: HexArray<0x70>,
+}
+
+impl XciHeader {
+    pub const SIZE: usize = 0x200;
+    pub const MEDIA_PAGE_SIZE: u64 = 0x200;
+
+    pub fn secure_area_start(&self) -> u64 {
+        self.secure_area_start_page as u64 * Self::MEDIA_PAGE_SIZE
+    }
+
+    /// Decrypts the info block at the end of the header with the `xci_header_key`.
+    pub fn decrypt_info(&self) -> SwonchResult<CardHeaderInfo> {
+        let key = KEYS.get_key::<Aes128Key>("xci_header_key")?;
+        let aes = Aes128::new(GenericArray::from_slice(&key.0));
+
+        // AES-128-CBC with the iv stored reversed in the header
+        let mut iv = self.iv.0;
+        iv.reverse();
+
+        let mut buf = self.encrypted_info.0;
+        for block in buf.chunks_exact_mut(0x10) {
+            let mut next_iv = [0; 0x10];
+            next_iv.copy_from_slice(block);
+
+            aes.decrypt_block(GenericArray::from_mut_slice(block));
+            block.iter_mut().zip(iv).for_each(|(b, iv)| *b ^= iv);
+
+            iv = next_iv;
+        }
+
+        CardHeaderInfo::read(&mut Cursor::new(&buf)).map_err(Into::into)
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessControlFlags(pub u32);
+
+impl AccessControlFlags {
+    /// Bus clock of the gamecard, in MHz.
+    pub fn clock_rate(&self) -> Option<u32> {
+        match self.0 {
+            0x00a10011 => Some(25),
+            0x00a10010 => Some(50),
+            _ => None,
+        }
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct FirmwareVersion(pub u64);
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "Development"),
+            1 => write!(f, "Retail 1.0.0"),
+            2 => write!(f, "Retail 4.0.0"),
+            3 => write!(f, "Retail 9.0.0"),
+            4 => write!(f, "Retail 11.0.0"),
+            5 => write!(f, "Retail 12.0.0"),
+            v => write!(f, "Unknown ({v})"),
+        }
+    }
+}
+
+/// The decrypted info block of an [`XciHeader`].
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct CardHeaderInfo {
+    pub firmware_version: FirmwareVersion,
+    pub access_control_flags: AccessControlFlags,
+    pub read_wait_time1: u32,
+    pub read_wait_time2: u32,
+    pub write_wait_time1: u32,
+    pub write_wait_time2: u32,
+    pub firmware_mode: u32,
+    pub cup_version: u32,
+    pub compatibility_type: u8,
+    pub reserved0: HexArray<3>,
+    pub update_partition_hash: HexArray<8>,
+    pub cup_id: ProgramId,
+    pub reserved1: HexArray<0x38>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hex_str_to_vec;
+
+    const XCI_HEADER_KEY: [u8; 0x10] = *b"xci_header_key!!";
+
+    // AES-128-CBC of the info block below, with the iv 0f0e..00
+    const ENCRYPTED_INFO: &str = concat!(
+        "37b0f45d16ec1155d2c1689c4aec4ff1",
+        "48bcf99ab6affd8a6decc71b00906f4a",
+        "8b38bc3152123ef54476d5f27eafbfed",
+        "da23c744087d9f6a1e5e91ead074b823",
+        "9b0dcfe58eaf302b52881e8811a8134a",
+        "47b5e78a0b9cc402c205c1f407c4bb86",
+        "1f9e5346ba4239ada8f3fad6acb52228",
+    );
+
+    #[test]
+    fn decrypt_info() -> SwonchResult<()> {
+        KEYS.insert_key("xci_header_key", XCI_HEADER_KEY, None);
+
+        let mut hdr = vec![0; XciHeader::SIZE];
+        hdr[0x100..0x104].copy_from_slice(b"HEAD");
+        hdr[0x10d] = 0xfa;
+        // stored reversed
+        hdr[0x120..0x130].copy_from_slice(&core::array::from_fn::<u8, 0x10, _>(|i| i as u8));
+        hdr[0x190..].copy_from_slice(&hex_str_to_vec(ENCRYPTED_INFO).expect("invalid hex"));
+        let hdr = XciHeader::read(&mut Cursor::new(&hdr))?;
+
+        let info = hdr.decrypt_info()?;
+        assert_eq!(info.firmware_version.to_string(), "Retail 4.0.0");
+        assert_eq!(info.access_control_flags.clock_rate(), Some(25));
+        assert_eq!(info.read_wait_time1, 0x1388);
+        assert_eq!(info.firmware_mode, 0x000c0101);
+        assert_eq!(info.cup_version, 0x0c100000);
+        assert_eq!(info.compatibility_type, 0);
+        assert_eq!(info.update_partition_hash.0, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(info.cup_id.to_string(), "0x0100000000000816");
+        assert_eq!(info.reserved1.0, [0; 0x38]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    containers::{
+        fs::{DirEntry, FileSystem, Metadata},
+        partitionfs::hfs0::Hfs0,
+    },
+    storage::{FromStorage, IStorage, Storage},
+    utils::{self, HexArray},
+    Integrity, SwonchResult,
+};
+use alloc::vec::Vec;
+use binrw::BinRead;
+use sha2::Sha256;
+
+pub mod header;
+pub use header::*;
+
+/// The named partitions inside of the root HFS0 of a gamecard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XciPartition {
+    Update,
+    Normal,
+    Secure,
+    Logo,
+}
+
+impl XciPartition {
+    pub fn name(&self) -> &'static str {
+        match self {
+            XciPartition::Update => "update",
+            XciPartition::Normal => "normal",
+            XciPartition::Secure => "secure",
+            XciPartition::Logo => "logo",
+        }
+    }
+}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum XciError {
+    #[error("no card header found, neither at the start nor after a key area")]
+    MissingHeader,
+
+    #[error("the {0} lies outside of the image")]
+    OutOfBounds(&'static str),
+
+    #[error("hash mismatch on the root HFS0 header")]
+    RootHeaderHashMismatch {
+        hash_in_header: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+    },
+}
+
+/// A gamecard image, with or without the key area prepended.
+///
+/// ```no_run
+/// use swonch::{prelude::*, containers::xci::Xci, storage::FileStorage, Integrity};
+///
+/// fn main() -> SwonchResult<()> {
+///     let xci = FileStorage::open("game.xci")?.map_to_storage::<Xci>(Integrity::default())?;
+///     let secure = xci.secure()?.expect("card has no secure partition");
+///
+///     for nca in secure.map_to_storage::<Hfs0>(())?.files() {
+///         println!("{}", nca.name());
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Xci {
+    storage: Storage,
+    header: XciHeader,
+    root: Hfs0,
+    has_key_area: bool,
+}
+
+impl Xci {
+    pub const KEY_AREA_SIZE: u64 = 0x200;
+    const HEADER_MAGIC_OFFSET: u64 = 0x100;
+
+    pub fn header(&self) -> &XciHeader {
+        &self.header
+    }
+
+    /// Decrypts the info block of the card header, see [`XciHeader::decrypt_info`].
+    pub fn card_info(&self) -> SwonchResult<CardHeaderInfo> {
+        self.header.decrypt_info()
+    }
+
+    /// Whether the image this was opened from had the key area prepended.
+    pub fn has_key_area(&self) -> bool {
+        self.has_key_area
+    }
+
+    /// The card image, starting at the card header regardless of a prepended key area.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn root(&self) -> &Hfs0 {
+        &self.root
+    }
+
+    pub fn partition(&self, partition: XciPartition) -> SwonchResult<Option<Storage>> {
+        self.root
+            .files()
+            .find(|e| e.name() == partition.name())
+            .map(|e| e.data())
+            .transpose()
+    }
+
+    pub fn update(&self) -> SwonchResult<Option<Storage>> {
+        self.partition(XciPartition::Update)
+    }
+
+    pub fn normal(&self) -> SwonchResult<Option<Storage>> {
+        self.partition(XciPartition::Normal)
+    }
+
+    pub fn secure(&self) -> SwonchResult<Option<Storage>> {
+        self.partition(XciPartition::Secure)
+    }
+
+    pub fn logo(&self) -> SwonchResult<Option<Storage>> {
+        self.partition(XciPartition::Logo)
+    }
+
+    fn has_magic_at(storage: &Storage, offset: u64) -> SwonchResult<bool> {
+        let mut magic = [0; 4];
+        let read = storage.read_at(offset, &mut magic)?;
+        Ok(read == 4 && &magic == b"HEAD")
+    }
+}
+
+/// Exposes the partitions of the root HFS0.
+impl FileSystem for Xci {
+    fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+        self.root.open_file(path)
+    }
+
+    fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+        self.root.read_dir(path)
+    }
+
+    fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+        self.root.metadata(path)
+    }
+}
+
+impl FromStorage for Xci {
+    type Args = Integrity;
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, integrity: Self::Args) -> Self::Output {
+        let has_key_area = if Xci::has_magic_at(&parent, Xci::HEADER_MAGIC_OFFSET)? {
+            false
+        } else if Xci::has_magic_at(&parent, Xci::KEY_AREA_SIZE + Xci::HEADER_MAGIC_OFFSET)? {
+            true
+        } else {
+            return Err(XciError::MissingHeader.into());
+        };
+
+        let storage = match has_key_area {
+            true => {
+                let len = parent
+                    .length()?
+                    .checked_sub(Xci::KEY_AREA_SIZE)
+                    .ok_or(XciError::OutOfBounds("key area"))?;
+                parent.split(Xci::KEY_AREA_SIZE, len)?
+            }
+            false => parent,
+        };
+
+        let header = XciHeader::read(&mut storage.clone().into_stdio())?;
+
+        // the offset and header size come straight from the image, check them before using them
+        let root_len = storage
+            .length()?
+            .checked_sub(header.root_hfs0_offset)
+            .ok_or(XciError::OutOfBounds("root HFS0"))?;
+        if header.root_hfs0_header_size > root_len {
+            return Err(XciError::OutOfBounds("root HFS0 header").into());
+        }
+
+        // validate root HFS0 header hash
+        let mut root_hdr = vec![0; header.root_hfs0_header_size as usize];
+        storage.read_at(header.root_hfs0_offset, &mut root_hdr)?;
+        let hash_is_valid =
+            utils::validate_hash::<Sha256>(&root_hdr, &header.root_hfs0_header_hash.0);
+        if let Err(hash) = hash_is_valid {
+            match integrity {
+                Integrity::WarnOnly => log::error!(
+                    "root HFS0 header hash mismatch. card header claims {:?} but actual hash is {}",
+                    header.root_hfs0_header_hash,
+                    HexArray::<0x20>(hash.into()),
+                ),
+                Integrity::ErrorOnMismatch => {
+                    return Err(XciError::RootHeaderHashMismatch {
+                        hash_in_header: header.root_hfs0_header_hash.0,
+                        actual_hash: hash.into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        let root = storage
+            .clone()
+            .split(header.root_hfs0_offset, root_len)?
+            .map_to_storage::<Hfs0>(())?;
+
+        Ok(Self {
+            storage,
+            header,
+            root,
+            has_key_area,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        containers::partitionfs::hfs0::tests::build_hfs0, storage::VecStorage, SwonchError,
+    };
+    use sha2::Digest;
+
+    const ROOT_OFFSET: u64 = 0x200;
+
+    // a card header followed by a root HFS0 containing an update and a secure partition
+    fn build_xci(rom_size: u8) -> Vec<u8> {
+        build_xci_with(
+            rom_size,
+            &[
+                ("update", b"update partition"),
+                ("secure", b"secure partition"),
+            ],
+        )
+    }
+
+    /// Builds a card header followed by a root HFS0 with the given partitions.
+    pub(crate) fn build_xci_with(rom_size: u8, partitions: &[(&str, &[u8])]) -> Vec<u8> {
+        let root = build_hfs0(partitions);
+        let names_size: usize = partitions.iter().map(|(name, _)| name.len() + 1).sum();
+        let root_header_size = 0x10 + partitions.len() * 0x40 + names_size;
+
+        let mut xci = vec![0; ROOT_OFFSET as usize];
+        xci[0x100..0x104].copy_from_slice(b"HEAD");
+        xci[0x10d] = rom_size;
+        xci[0x130..0x138].copy_from_slice(&ROOT_OFFSET.to_le_bytes());
+        xci[0x138..0x140].copy_from_slice(&(root_header_size as u64).to_le_bytes());
+        xci[0x140..0x160].copy_from_slice(&Sha256::digest(&root[..root_header_size]));
+
+        xci.extend(root);
+        xci
+    }
+
+    fn open(xci: Vec<u8>) -> SwonchResult<Xci> {
+        VecStorage::new(xci).map_to_storage::<Xci>(Integrity::ErrorOnMismatch)
+    }
+
+    fn read_partition(xci: &Xci, partition: XciPartition) -> SwonchResult<Option<Vec<u8>>> {
+        let Some(data) = xci.partition(partition)? else {
+            return Ok(None);
+        };
+        let mut buf = vec![0; data.length()? as usize];
+        data.read_at(0, &mut buf)?;
+        Ok(Some(buf))
+    }
+
+    #[test]
+    fn open_partitions() -> SwonchResult<()> {
+        let xci = build_xci(0xf8);
+        let mut with_key_area = vec![0; Xci::KEY_AREA_SIZE as usize];
+        with_key_area.extend(&xci);
+
+        for (xci, has_key_area) in [(open(xci)?, false), (open(with_key_area)?, true)] {
+            assert_eq!(xci.has_key_area(), has_key_area);
+            assert_eq!(xci.header().rom_size, RomSize::Size2GB);
+            assert_eq!(
+                read_partition(&xci, XciPartition::Secure)?.as_deref(),
+                Some(&b"secure partition"[..])
+            );
+            assert_eq!(
+                read_partition(&xci, XciPartition::Update)?.as_deref(),
+                Some(&b"update partition"[..])
+            );
+            assert!(xci.normal()?.is_none());
+        }
+
+        let xci = open(build_xci(0x42))?;
+        assert_eq!(xci.header().rom_size, RomSize::Unknown(0x42));
+
+        Ok(())
+    }
+
+    #[test]
+    fn root_header_hash_mismatch() -> SwonchResult<()> {
+        let mut xci = build_xci(0xfa);
+        xci[0x140] ^= 0xff;
+
+        assert!(matches!(
+            open(xci.clone()),
+            Err(SwonchError::Xci(XciError::RootHeaderHashMismatch { .. }))
+        ));
+        assert!(VecStorage::new(xci)
+            .map_to_storage::<Xci>(Integrity::WarnOnly)
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_out_of_bounds_root() {
+        let mut xci = build_xci(0xfa);
+        xci[0x130..0x138].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            open(xci),
+            Err(SwonchError::Xci(XciError::OutOfBounds("root HFS0")))
+        ));
+
+        // a huge header size isn't allocated
+        let mut xci = build_xci(0xfa);
+        xci[0x138..0x140].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            open(xci),
+            Err(SwonchError::Xci(XciError::OutOfBounds("root HFS0 header")))
+        ));
+
+        // no card header at all
+        assert!(matches!(
+            open(vec![0; 0x400]),
+            Err(SwonchError::Xci(XciError::MissingHeader))
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! Sniffing the content type of a [`Storage`] by its magic and structure.
+//!
+//! ```
+//! use swonch::{detect::{self, ContainerKind}, prelude::*};
+//!
+//! let pfs0 = VecStorage::new(b"PFS0\0\0\0\0\0\0\0\0\0\0\0\0".to_vec());
+//! assert_eq!(detect::detect(&pfs0), Some(ContainerKind::Pfs0));
+//! ```
+
+use crate::{
+    containers::nca::NcaHeader,
+    storage::{IStorage, Storage},
+    sync_impl::RwLock,
+};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    Pfs0,
+    Hfs0,
+    Xci,
+    Nca,
+    RomFs,
+    Nso,
+    Nro,
+    Npdm,
+    Nacp,
+    Cnmt,
+    Ticket,
+    Certificate,
+    /// Reported by a detector registered with [`register_detector`].
+    Custom(&'static str),
+}
+
+impl fmt::Display for ContainerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ContainerKind::Pfs0 => "PFS0",
+                ContainerKind::Hfs0 => "HFS0",
+                ContainerKind::Xci => "XCI",
+                ContainerKind::Nca => "NCA",
+                ContainerKind::RomFs => "RomFS",
+                ContainerKind::Nso => "NSO",
+                ContainerKind::Nro => "NRO",
+                ContainerKind::Npdm => "NPDM",
+                ContainerKind::Nacp => "NACP",
+                ContainerKind::Cnmt => "CNMT",
+                ContainerKind::Ticket => "Ticket",
+                ContainerKind::Certificate => "Certificate",
+                ContainerKind::Custom(name) => name,
+            }
+        )
+    }
+}
+
+/// What a detector gets to look at: the storage itself and its first few bytes.
+pub struct Probe<'a> {
+    pub storage: &'a Storage,
+    /// Up to the first [`Probe::HEAD_SIZE`] bytes of the storage.
+    pub head: &'a [u8],
+    pub length: u64,
+}
+
+impl<'a> Probe<'a> {
+    pub const HEAD_SIZE: usize = 0x400;
+
+    fn magic_at(&self, offset: usize, magic: &[u8]) -> bool {
+        self.head.get(offset..offset + magic.len()) == Some(magic)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.head.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_at(&self, offset: usize) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            self.head.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+}
+
+/// A function recognising a content type, returns `None` if it doesn't match.
+pub type Detector = fn(&Probe) -> Option<ContainerKind>;
+
+lazy_static::lazy_static! {
+    static ref DETECTORS: RwLock<Vec<Detector>> = RwLock::new(Vec::new());
+}
+
+/// Registers an additional detector. Registered detectors are only consulted if none
+/// of the builtin ones recognised the storage, in the order they were registered.
+pub fn register_detector(detector: Detector) {
+    DETECTORS.write().push(detector);
+}
+
+/// Detects what a storage contains, `None` if nothing recognised it.
+pub fn detect(storage: &Storage) -> Option<ContainerKind> {
+    let mut head = [0; Probe::HEAD_SIZE];
+    let read = storage.read_at(0, &mut head).ok()? as usize;
+    let probe = Probe {
+        storage,
+        head: &head[..read],
+        length: storage.length().ok()?,
+    };
+
+    BUILTIN_DETECTORS
+        .iter()
+        .chain(DETECTORS.read().iter())
+        .find_map(|detector| detector(&probe))
+}
+
+const BUILTIN_DETECTORS: &[Detector] = &[
+    detect_magic,
+    detect_xci,
+    detect_romfs,
+    // the remaining formats have no magic to go by, rule out an encrypted NCA first
+    detect_nca,
+    detect_cnmt,
+    detect_signed,
+    detect_nacp,
+];
+
+fn detect_magic(probe: &Probe) -> Option<ContainerKind> {
+    match probe.head.get(..4)? {
+        b"PFS0" => Some(ContainerKind::Pfs0),
+        b"HFS0" => Some(ContainerKind::Hfs0),
+        b"NSO0" => Some(ContainerKind::Nso),
+        b"META" => Some(ContainerKind::Npdm),
+        _ if probe.magic_at(0x10, b"NRO0") => Some(ContainerKind::Nro),
+        _ => None,
+    }
+}
+
+fn detect_xci(probe: &Probe) -> Option<ContainerKind> {
+    // with or without the key area prepended
+    (probe.magic_at(0x100, b"HEAD") || probe.magic_at(0x300, b"HEAD")).then_some(ContainerKind::Xci)
+}
+
+fn detect_romfs(probe: &Probe) -> Option<ContainerKind> {
+    if probe.u64_at(0)? != 0x50 {
+        return None;
+    }
+
+    // every table and the data have to be inside of the storage
+    let tables_fit = (0..4).all(|i| {
+        let (Some(offset), Some(size)) =
+            (probe.u64_at(0x8 + i * 0x10), probe.u64_at(0x10 + i * 0x10))
+        else {
+            return false;
+        };
+        offset
+            .checked_add(size)
+            .is_some_and(|end| end <= probe.length)
+    });
+
+    (tables_fit && probe.u64_at(0x48)? <= probe.length).then_some(ContainerKind::RomFs)
+}
+
+fn detect_cnmt(probe: &Probe) -> Option<ContainerKind> {
+    const HEADER_SIZE: u64 = 0x20;
+    const CONTENT_RECORD_SIZE: u64 = 0x38;
+    const META_RECORD_SIZE: u64 = 0x10;
+    const DIGEST_SIZE: u64 = 0x20;
+
+    let title_id = probe.u64_at(0)?;
+    let meta_type = *probe.head.get(0xc)?;
+    let ext_header_size = probe.u16_at(0xe)? as u64;
+    let content_cnt = probe.u16_at(0x10)? as u64;
+    let meta_cnt = probe.u16_at(0x12)? as u64;
+
+    // the extended header size is fixed per meta type
+    let known_type = matches!(
+        (meta_type, ext_header_size),
+        (0x1..=0x2 | 0x4..=0x5, 0)
+            | (0x3, 0 | 0x4)
+            | (0x80 | 0x83, 0x10)
+            | (0x81, 0x18)
+            | (0x82, 0x10 | 0x18)
+            | (0x84, 0x18)
+    );
+    let min_size = HEADER_SIZE
+        + ext_header_size
+        + content_cnt * CONTENT_RECORD_SIZE
+        + meta_cnt * META_RECORD_SIZE
+        + DIGEST_SIZE;
+
+    (title_id != 0 && known_type && min_size <= probe.length).then_some(ContainerKind::Cnmt)
+}
+
+fn detect_signed(probe: &Probe) -> Option<ContainerKind> {
+    // signature type is big endian, followed by the signature and padding depending on the type
+    let sig_type = u32::from_be_bytes(probe.head.get(..4)?.try_into().ok()?);
+    let (sig_size, padding) = match sig_type {
+        0x010000 | 0x010003 => (0x200, 0x3c), // RSA-4096
+        0x010001 | 0x010004 => (0x100, 0x3c), // RSA-2048
+        0x010002 | 0x010005 => (0x3c, 0x40),  // ECDSA
+        _ => return None,
+    };
+    let issuer_offset = 4 + sig_size + padding;
+    let issuer = probe.head.get(issuer_offset..issuer_offset + 0x40)?;
+
+    if !issuer.starts_with(b"Root") {
+        return None;
+    }
+
+    // tickets are issued by the XS (ticket signing) certificates
+    if issuer.windows(3).any(|w| w == b"-XS") {
+        return Some(ContainerKind::Ticket);
+    }
+
+    let key_type_offset = issuer_offset + 0x40;
+    let key_type = u32::from_be_bytes(
+        probe
+            .head
+            .get(key_type_offset..key_type_offset + 4)?
+            .try_into()
+            .ok()?,
+    );
+    (key_type <= 2).then_some(ContainerKind::Certificate)
+}
+
+fn detect_nacp(probe: &Probe) -> Option<ContainerKind> {
+    const NACP_SIZE: u64 = 0x4000;
+    const DISPLAY_VERSION_OFFSET: u64 = 0x3060;
+
+    if probe.length != NACP_SIZE {
+        return None;
+    }
+
+    // a NACP has no magic, but the display version has to be a printable, non-empty string
+    let mut version = [0; 0x10];
+    probe
+        .storage
+        .read_at(DISPLAY_VERSION_OFFSET, &mut version)
+        .ok()?;
+    let len = version.iter().position(|c| *c == 0)?;
+
+    (len > 0 && version[..len].iter().all(u8::is_ascii_graphic)).then_some(ContainerKind::Nacp)
+}
+
+fn detect_nca(probe: &Probe) -> Option<ContainerKind> {
+    let mut hdr: [u8; 0x400] = probe.head.get(..0x400)?.try_into().ok()?;
+
+    NcaHeader::from_buf(&mut hdr)
+        .is_ok()
+        .then_some(ContainerKind::Nca)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        containers::{nca, romfs},
+        storage::VecStorage,
+        SwonchResult,
+    };
+
+    const SIGNATURE_TYPES: [(u32, usize); 6] = [
+        (0x010000, 0x240),
+        (0x010001, 0x140),
+        (0x010002, 0x80),
+        (0x010003, 0x240),
+        (0x010004, 0x140),
+        (0x010005, 0x80),
+    ];
+
+    fn signed(sig_type: u32, issuer_offset: usize, issuer: &[u8], key_type: u32) -> Storage {
+        let mut buf = vec![0; 0x400];
+        buf[..4].copy_from_slice(&sig_type.to_be_bytes());
+        buf[issuer_offset..][..issuer.len()].copy_from_slice(issuer);
+        buf[issuer_offset + 0x40..][..4].copy_from_slice(&key_type.to_be_bytes());
+        VecStorage::new(buf)
+    }
+
+    #[test]
+    fn detect_signed_per_signature_type() {
+        for (sig_type, issuer_offset) in SIGNATURE_TYPES {
+            let ticket = signed(sig_type, issuer_offset, b"Root-CA00000003-XS00000020", 0);
+            assert_eq!(
+                detect(&ticket),
+                Some(ContainerKind::Ticket),
+                "{sig_type:#x}"
+            );
+
+            let cert = signed(sig_type, issuer_offset, b"Root-CA00000003", 1);
+            assert_eq!(
+                detect(&cert),
+                Some(ContainerKind::Certificate),
+                "{sig_type:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn ecdsa_issuer_is_not_at_0x40() {
+        for sig_type in [0x010002, 0x010005] {
+            let ticket = signed(sig_type, 0x40, b"Root-CA00000003-XS00000020", 0);
+            assert_eq!(detect(&ticket), None, "{sig_type:#x}");
+        }
+    }
+
+    #[test]
+    fn detect_xci() {
+        for header_offset in [0x100, 0x300] {
+            let mut xci = vec![0; 0x400];
+            xci[header_offset..][..4].copy_from_slice(b"HEAD");
+            assert_eq!(
+                detect(&VecStorage::new(xci)),
+                Some(ContainerKind::Xci),
+                "{header_offset:#x}"
+            );
+        }
+
+        // the magic somewhere else in the header
+        let mut xci = vec![0; 0x400];
+        xci[0x200..0x204].copy_from_slice(b"HEAD");
+        assert_eq!(detect(&VecStorage::new(xci)), None);
+    }
+
+    #[test]
+    fn detect_romfs() -> SwonchResult<()> {
+        let raw = romfs::tests::build_romfs(0x50)?;
+        assert_eq!(
+            detect(&VecStorage::new(raw.clone())),
+            Some(ContainerKind::RomFs)
+        );
+
+        // the file metadata table and the data running past the end of the storage
+        for field in [0x40, 0x48] {
+            let mut raw = raw.clone();
+            let len = raw.len() as u64 + 1;
+            raw[field..][..8].copy_from_slice(&len.to_le_bytes());
+            assert_eq!(detect(&VecStorage::new(raw)), None, "{field:#x}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_nca() {
+        let raw = nca::tests::build_nca(&[0; 0x200]);
+        assert_eq!(
+            detect(&VecStorage::new(raw.clone())),
+            Some(ContainerKind::Nca)
+        );
+
+        // an unknown version can't be told apart from an encrypted header
+        let mut raw = raw;
+        raw[0x203] = b'4';
+        assert_eq!(detect(&VecStorage::new(raw)), None);
+    }
+
+    // an application CNMT with a single content record
+    fn build_cnmt() -> Vec<u8> {
+        let mut cnmt = vec![0; 0x20 + 0x10 + 0x38 + 0x20];
+        cnmt[..8].copy_from_slice(&0x0100_0000_0001_0000u64.to_le_bytes());
+        cnmt[0xc] = 0x80;
+        cnmt[0xe..0x10].copy_from_slice(&0x10u16.to_le_bytes());
+        cnmt[0x10..0x12].copy_from_slice(&1u16.to_le_bytes());
+        cnmt
+    }
+
+    #[test]
+    fn detect_cnmt() {
+        assert_eq!(
+            detect(&VecStorage::new(build_cnmt())),
+            Some(ContainerKind::Cnmt)
+        );
+
+        // the extended header size of a patch, the digest cut off and no title id
+        let mut wrong_ext_header = build_cnmt();
+        wrong_ext_header[0xe] = 0x18;
+        let mut truncated = build_cnmt();
+        truncated.pop();
+        let mut no_title_id = build_cnmt();
+        no_title_id[..8].fill(0);
+
+        for cnmt in [wrong_ext_header, truncated, no_title_id] {
+            assert_eq!(detect(&VecStorage::new(cnmt)), None);
+        }
+    }
+
+    fn build_nacp(display_version: &[u8]) -> Vec<u8> {
+        let mut nacp = vec![0; 0x4000];
+        nacp[0x3060..][..display_version.len()].copy_from_slice(display_version);
+        nacp
+    }
+
+    #[test]
+    fn detect_nacp() {
+        assert_eq!(
+            detect(&VecStorage::new(build_nacp(b"1.0.2"))),
+            Some(ContainerKind::Nacp)
+        );
+
+        // an empty, unprintable or unterminated display version, or the wrong size
+        let mut too_long = build_nacp(b"1.0.2");
+        too_long.push(0);
+        for nacp in [
+            build_nacp(b""),
+            build_nacp(b"1.0\n2"),
+            build_nacp(&[b'1'; 0x10]),
+            too_long,
+        ] {
+            assert_eq!(detect(&VecStorage::new(nacp)), None);
+        }
+    }
+}
@@ -12,12 +12,45 @@ pub enum SwonchError {
     #[error("error with an nca")]
     Nca(#[from] crate::containers::nca::NcaError),
 
+    #[error("filesystem error")]
+    Fs(#[from] crate::containers::fs::FsError),
+
+    #[error("error with a partition filesystem")]
+    PartitionFs(#[from] crate::containers::partitionfs::PartitionFsError),
+
+    #[error("error with a RomFS")]
+    RomFs(#[from] crate::containers::romfs::RomFsError),
+
+    #[error("error with a gamecard image")]
+    Xci(#[from] crate::containers::xci::XciError),
+
+    #[error("error with a content meta")]
+    ContentMeta(#[from] crate::formats::cnmt::ContentMetaError),
+
+    #[error("error with an NPDM")]
+    Npdm(#[from] crate::formats::npdm::NpdmError),
+
+    #[error("error with an NSO")]
+    Nso(#[from] crate::formats::nso::NsoError),
+
     #[error("substorage error")]
     SubStorage(#[from] crate::storage::substorage::SubStorageError),
 
+    #[error("integrity verification failed")]
+    Integrity(#[from] crate::storage::integrity::IntegrityError),
+
+    #[error("decompression error")]
+    Compression(#[from] crate::storage::compressed::CompressionError),
+
+    #[error("bucket tree error")]
+    BucketTree(#[from] crate::storage::bucket_tree::BucketTreeError),
+
     #[error("key related error")]
     Keyset(#[from] crate::keyset::KeyError),
 
+    #[error("error resolving a virtual path")]
+    Vfs(#[from] crate::vfs::VfsError),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -0,0 +1,348 @@
+//! The content meta (`.cnmt`) in the PartitionFS of Meta NCAs, listing the contents and other
+//! titles that make up a title.
+
+use crate::{
+    common::{ContentId, ProgramId},
+    storage::{FromStorage, IStorage, Storage},
+    utils::HexArray,
+    SwonchResult,
+};
+use alloc::vec::Vec;
+use binrw::{io::Cursor, BinRead};
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ContentMetaType {
+    SystemProgram = 0x1,
+    SystemData = 0x2,
+    SystemUpdate = 0x3,
+    BootImagePackage = 0x4,
+    BootImagePackageSafe = 0x5,
+    Application = 0x80,
+    Patch = 0x81,
+    AddOnContent = 0x82,
+    Delta = 0x83,
+    DataPatch = 0x84, // 15.0.0+
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct ContentMetaHeader {
+    pub title_id: ProgramId,
+    pub version: u32,
+    pub meta_type: ContentMetaType,
+    pub platform: u8, // 17.0.0+
+    pub extended_header_size: u16,
+    pub content_count: u16,
+    pub content_meta_count: u16,
+    pub attributes: u8,
+    reserved0: [u8; 3],
+    pub required_download_system_version: u32,
+    reserved1: u32,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct ApplicationMetaExtendedHeader {
+    pub patch_id: ProgramId,
+    pub required_system_version: u32,
+    pub required_application_version: u32,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct PatchMetaExtendedHeader {
+    pub application_id: ProgramId,
+    pub required_system_version: u32,
+    pub extended_data_size: u32,
+    reserved: u64,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[br(import(size: usize))]
+#[derive(Debug, Clone)]
+pub struct AddOnContentMetaExtendedHeader {
+    pub application_id: ProgramId,
+    pub required_application_version: u32,
+    pub content_accessibilities: u8,
+    reserved: [u8; 3],
+    /// Only present in the larger header used since 15.0.0.
+    #[br(if(size >= 0x18))]
+    pub data_patch_id: Option<ProgramId>,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct DeltaMetaExtendedHeader {
+    pub application_id: ProgramId,
+    pub extended_data_size: u32,
+    reserved: u32,
+}
+
+/// The extended header following the [`ContentMetaHeader`], depending on the [`ContentMetaType`].
+#[derive(Debug, Clone)]
+pub enum ExtendedHeader {
+    Application(ApplicationMetaExtendedHeader),
+    Patch(PatchMetaExtendedHeader),
+    AddOnContent(AddOnContentMetaExtendedHeader),
+    Delta(DeltaMetaExtendedHeader),
+    /// Any other extended header, kept as is.
+    Other(Vec<u8>),
+}
+
+impl ExtendedHeader {
+    fn read(meta_type: ContentMetaType, raw: &[u8]) -> SwonchResult<Self> {
+        let mut cursor = Cursor::new(raw);
+
+        Ok(match meta_type {
+            ContentMetaType::Application => {
+                Self::Application(ApplicationMetaExtendedHeader::read(&mut cursor)?)
+            }
+            ContentMetaType::Patch => Self::Patch(PatchMetaExtendedHeader::read(&mut cursor)?),
+            ContentMetaType::AddOnContent => Self::AddOnContent(
+                AddOnContentMetaExtendedHeader::read_args(&mut cursor, (raw.len(),))?,
+            ),
+            ContentMetaType::Delta => Self::Delta(DeltaMetaExtendedHeader::read(&mut cursor)?),
+            _ => Self::Other(raw.into()),
+        })
+    }
+}
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ContentType {
+    Meta = 0,
+    Program = 1,
+    Data = 2,
+    Control = 3,
+    HtmlDocument = 4,
+    LegalInformation = 5,
+    DeltaFragment = 6,
+}
+
+/// A content of the title, usually an NCA named after its [`ContentId`].
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct ContentRecord {
+    /// SHA-256 over the whole content.
+    pub hash: HexArray<0x20>,
+    pub content_id: ContentId,
+    size: [u8; 5],
+    pub content_attributes: u8,
+    pub content_type: ContentType,
+    /// Added to the title ID for contents belonging to e.g. a specific program of a multi program application.
+    pub id_offset: u8,
+}
+
+impl ContentRecord {
+    pub fn size(&self) -> u64 {
+        let mut size = [0; 8];
+        size[..5].copy_from_slice(&self.size);
+        u64::from_le_bytes(size)
+    }
+}
+
+/// Another title referenced by this one, e.g. the titles installed by a system update.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct ContentMetaRecord {
+    pub title_id: ProgramId,
+    pub version: u32,
+    pub meta_type: ContentMetaType,
+    pub attributes: u8,
+    reserved: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentMeta {
+    header: ContentMetaHeader,
+    extended_header: ExtendedHeader,
+    contents: Vec<ContentRecord>,
+    content_metas: Vec<ContentMetaRecord>,
+    extended_data: Vec<u8>,
+    digest: HexArray<0x20>,
+}
+
+impl ContentMeta {
+    const HEADER_SIZE: usize = 0x20;
+    const DIGEST_SIZE: usize = 0x20;
+
+    pub fn header(&self) -> &ContentMetaHeader {
+        &self.header
+    }
+
+    pub fn extended_header(&self) -> &ExtendedHeader {
+        &self.extended_header
+    }
+
+    pub fn contents(&self) -> &[ContentRecord] {
+        &self.contents
+    }
+
+    pub fn content_metas(&self) -> &[ContentMetaRecord] {
+        &self.content_metas
+    }
+
+    /// Data behind the records, e.g. the fragment sets of patches and deltas.
+    pub fn extended_data(&self) -> &[u8] {
+        &self.extended_data
+    }
+
+    pub fn digest(&self) -> &HexArray<0x20> {
+        &self.digest
+    }
+
+    /// The application this title belongs to, or the application itself.
+    pub fn application_id(&self) -> Option<ProgramId> {
+        match &self.extended_header {
+            ExtendedHeader::Application(_) => Some(self.header.title_id),
+            ExtendedHeader::Patch(hdr) => Some(hdr.application_id),
+            ExtendedHeader::AddOnContent(hdr) => Some(hdr.application_id),
+            ExtendedHeader::Delta(hdr) => Some(hdr.application_id),
+            ExtendedHeader::Other(_) => None,
+        }
+    }
+
+    /// The minimum system version needed to run the title, only known for applications and patches.
+    pub fn required_system_version(&self) -> Option<u32> {
+        match &self.extended_header {
+            ExtendedHeader::Application(hdr) => Some(hdr.required_system_version),
+            ExtendedHeader::Patch(hdr) => Some(hdr.required_system_version),
+            _ => None,
+        }
+    }
+
+    /// The first content of the given type, e.g. the Program NCA of an application.
+    pub fn content_by_type(&self, content_type: ContentType) -> Option<&ContentRecord> {
+        self.contents
+            .iter()
+            .find(|c| c.content_type == content_type)
+    }
+}
+
+impl FromStorage for ContentMeta {
+    type Args = ();
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, _: Self::Args) -> Self::Output {
+        let mut buf = vec![0; parent.length()? as usize];
+        parent.read_at(0, &mut buf)?;
+
+        let mut cursor = Cursor::new(&buf);
+        let header = ContentMetaHeader::read(&mut cursor)?;
+
+        let ext_header_end = Self::HEADER_SIZE + header.extended_header_size as usize;
+        let extended_header = ExtendedHeader::read(
+            header.meta_type,
+            buf.get(Self::HEADER_SIZE..ext_header_end)
+                .ok_or(ContentMetaError::Truncated)?,
+        )?;
+
+        cursor.set_position(ext_header_end as u64);
+        let contents = (0..header.content_count)
+            .map(|_| ContentRecord::read(&mut cursor))
+            .collect::<Result<Vec<_>, _>>()?;
+        let content_metas = (0..header.content_meta_count)
+            .map(|_| ContentMetaRecord::read(&mut cursor))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // whatever is left in front of the digest is the extended data
+        let records_end = cursor.position() as usize;
+        let digest_start = buf
+            .len()
+            .checked_sub(Self::DIGEST_SIZE)
+            .filter(|start| *start >= records_end)
+            .ok_or(ContentMetaError::Truncated)?;
+        let mut digest = [0; Self::DIGEST_SIZE];
+        digest.copy_from_slice(&buf[digest_start..]);
+
+        Ok(Self {
+            header,
+            extended_header,
+            contents,
+            content_metas,
+            extended_data: buf[records_end..digest_start].into(),
+            digest: HexArray(digest),
+        })
+    }
+}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum ContentMetaError {
+    #[error("the content meta ends before its digest")]
+    Truncated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        detect::{self, ContainerKind},
+        storage::VecStorage,
+    };
+
+    fn build_cnmt() -> Vec<u8> {
+        let mut cnmt = vec![];
+        cnmt.extend(0x0100_1234_5678_0000u64.to_le_bytes());
+        cnmt.extend(0x10000u32.to_le_bytes());
+        cnmt.extend([0x80, 0]);
+        cnmt.extend(0x10u16.to_le_bytes());
+        cnmt.extend(1u16.to_le_bytes());
+        cnmt.extend(0u16.to_le_bytes());
+        cnmt.extend([0; 0xc]);
+
+        // application extended header
+        cnmt.extend(0x0100_1234_5678_0800u64.to_le_bytes());
+        cnmt.extend(0x0c00_0000u32.to_le_bytes());
+        cnmt.extend(0u32.to_le_bytes());
+
+        // a single Program NCA of 0x1_0000_0200 bytes
+        cnmt.extend([0xaa; 0x20]);
+        cnmt.extend(0xcafebabe_deadbeef_00112233_44556677u128.to_be_bytes());
+        cnmt.extend(&0x1_0000_0200u64.to_le_bytes()[..5]);
+        // content attributes, which must not end up in the size
+        cnmt.push(0x01);
+        cnmt.extend([ContentType::Program as u8, 0]);
+
+        cnmt.extend([0xdd; 0x20]);
+        cnmt
+    }
+
+    #[test]
+    fn parse_application() -> SwonchResult<()> {
+        let storage = VecStorage::new(build_cnmt());
+        assert_eq!(detect::detect(&storage), Some(ContainerKind::Cnmt));
+
+        let cnmt = storage.map_to_storage::<ContentMeta>(())?;
+        assert_eq!(cnmt.header().meta_type, ContentMetaType::Application);
+        assert_eq!(cnmt.header().version, 0x10000);
+        assert_eq!(cnmt.application_id(), Some(cnmt.header().title_id));
+        assert_eq!(cnmt.required_system_version(), Some(0x0c00_0000));
+
+        let program = cnmt
+            .content_by_type(ContentType::Program)
+            .expect("program content not found");
+        assert_eq!(
+            program.content_id,
+            ContentId(0xcafebabe_deadbeef_00112233_44556677)
+        );
+        assert_eq!(program.size(), 0x1_0000_0200);
+        assert_eq!(program.content_attributes, 0x01);
+        assert!(cnmt.content_metas().is_empty());
+        assert!(cnmt.extended_data().is_empty());
+        assert_eq!(cnmt.digest().0, [0xdd; 0x20]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+//! Parsers for the files found inside of the containers, like the metadata of titles.
+
+use bstr::{BStr, ByteSlice};
+
+pub mod cnmt;
+pub mod nacp;
+pub mod npdm;
+pub mod nro;
+pub mod nso;
+
+/// The part of a fixed size, null terminated string in front of the terminator.
+pub(crate) fn c_str(buf: &[u8]) -> &BStr {
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    buf[..len].as_bstr()
+}
@@ -0,0 +1,349 @@
+//! The application control property (`control.nacp`) in the RomFS of Control NCAs, containing
+//! the names, version and settings of an application. The icons are stored next to it.
+
+use crate::{
+    containers::FileSystem,
+    storage::{FromStorage, Storage},
+    utils::HexArray,
+    SwonchResult,
+};
+use binrw::BinRead;
+use bstr::BStr;
+use core::fmt;
+
+use super::c_str;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    AmericanEnglish = 0,
+    BritishEnglish = 1,
+    Japanese = 2,
+    French = 3,
+    German = 4,
+    LatinAmericanSpanish = 5,
+    Spanish = 6,
+    Italian = 7,
+    Dutch = 8,
+    CanadianFrench = 9,
+    Portuguese = 10,
+    Russian = 11,
+    Korean = 12,
+    TraditionalChinese = 13,
+    SimplifiedChinese = 14,
+    BrazilianPortuguese = 15,
+}
+
+impl Language {
+    pub const ALL: [Language; 16] = {
+        use Language::*;
+        [
+            AmericanEnglish,
+            BritishEnglish,
+            Japanese,
+            French,
+            German,
+            LatinAmericanSpanish,
+            Spanish,
+            Italian,
+            Dutch,
+            CanadianFrench,
+            Portuguese,
+            Russian,
+            Korean,
+            TraditionalChinese,
+            SimplifiedChinese,
+            BrazilianPortuguese,
+        ]
+    };
+
+    /// Name of the JPEG icon for this language in the RomFS of the Control NCA.
+    pub fn icon_file_name(&self) -> &'static str {
+        use Language::*;
+        match self {
+            AmericanEnglish => "icon_AmericanEnglish.dat",
+            BritishEnglish => "icon_BritishEnglish.dat",
+            Japanese => "icon_Japanese.dat",
+            French => "icon_French.dat",
+            German => "icon_German.dat",
+            LatinAmericanSpanish => "icon_LatinAmericanSpanish.dat",
+            Spanish => "icon_Spanish.dat",
+            Italian => "icon_Italian.dat",
+            Dutch => "icon_Dutch.dat",
+            CanadianFrench => "icon_CanadianFrench.dat",
+            Portuguese => "icon_Portuguese.dat",
+            Russian => "icon_Russian.dat",
+            Korean => "icon_Korean.dat",
+            TraditionalChinese => "icon_TraditionalChinese.dat",
+            SimplifiedChinese => "icon_SimplifiedChinese.dat",
+            BrazilianPortuguese => "icon_BrazilianPortuguese.dat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RatingOrganization {
+    CERO = 0,
+    GRACGCRB = 1,
+    GSRMR = 2,
+    ESRB = 3,
+    ClassInd = 4,
+    USK = 5,
+    PEGI = 6,
+    PEGIPortugal = 7,
+    PEGIBBFC = 8,
+    Russian = 9,
+    ACB = 10,
+    OFLC = 11,
+    IARCGeneric = 12,
+}
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StartupUserAccount {
+    None = 0,
+    Required = 1,
+    RequiredWithNetworkServiceAccountAvailable = 2,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Clone)]
+pub struct ApplicationTitle {
+    name: [u8; 0x200],
+    publisher: [u8; 0x100],
+}
+
+impl ApplicationTitle {
+    pub fn name(&self) -> &BStr {
+        c_str(&self.name)
+    }
+
+    pub fn publisher(&self) -> &BStr {
+        c_str(&self.publisher)
+    }
+}
+
+impl fmt::Debug for ApplicationTitle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApplicationTitle")
+            .field("name", &self.name())
+            .field("publisher", &self.publisher())
+            .finish()
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Clone)]
+pub struct Nacp {
+    titles: [ApplicationTitle; 0x10],
+    isbn: [u8; 0x25],
+    pub startup_user_account: StartupUserAccount,
+    pub user_account_switch_lock: u8,
+    pub add_on_content_registration_type: u8,
+    pub attribute_flag: u32,
+    pub supported_language_flag: u32,
+    pub parental_control_flag: u32,
+    pub screenshot: u8,
+    pub video_capture: u8,
+    pub data_loss_confirmation: u8,
+    pub play_log_policy: u8,
+    pub presence_group_id: u64,
+    rating_age: [i8; 0x20],
+    display_version: [u8; 0x10],
+    pub add_on_content_base_id: u64,
+    pub save_data_owner_id: u64,
+    pub user_account_save_data_size: i64,
+    pub user_account_save_data_journal_size: i64,
+    pub device_save_data_size: i64,
+    pub device_save_data_journal_size: i64,
+    pub bcat_delivery_cache_storage_size: i64,
+    application_error_code_category: [u8; 8],
+    pub local_communication_ids: [u64; 8],
+    pub logo_type: u8,
+    pub logo_handling: u8,
+    pub runtime_add_on_content_install: u8,
+    pub runtime_parameter_delivery: u8,
+    reserved0: [u8; 2],
+    pub crash_report: u8,
+    pub hdcp: u8,
+    pub seed_for_pseudo_device_id: u64,
+    bcat_passphrase: [u8; 0x41],
+    pub startup_user_account_option: u8,
+    reserved1: [u8; 6],
+    pub user_account_save_data_size_max: i64,
+    pub user_account_save_data_journal_size_max: i64,
+    pub device_save_data_size_max: i64,
+    pub device_save_data_journal_size_max: i64,
+    pub temporary_storage_size: i64,
+    pub cache_storage_size: i64,
+    pub cache_storage_journal_size: i64,
+    pub cache_storage_data_and_journal_size_max: i64,
+    pub cache_storage_index_max: u16,
+    // newer fields like the play log and network service settings
+    reserved2: HexArray<0xe76>,
+}
+
+impl Nacp {
+    /// The title in the given language, `None` if there is none.
+    pub fn title(&self, language: Language) -> Option<&ApplicationTitle> {
+        let title = &self.titles[language as usize];
+        (!title.name().is_empty()).then_some(title)
+    }
+
+    /// The title of the first supported language that has one, usually American English.
+    pub fn default_title(&self) -> Option<&ApplicationTitle> {
+        self.supported_languages()
+            .chain(Language::ALL)
+            .find_map(|language| self.title(language))
+    }
+
+    pub fn supported_languages(&self) -> impl Iterator<Item = Language> + '_ {
+        Language::ALL
+            .into_iter()
+            .filter(|language| self.supported_language_flag & (1 << *language as u8) != 0)
+    }
+
+    pub fn isbn(&self) -> &BStr {
+        c_str(&self.isbn)
+    }
+
+    /// The version shown to the user, e.g. `1.0.2`.
+    pub fn display_version(&self) -> &BStr {
+        c_str(&self.display_version)
+    }
+
+    pub fn application_error_code_category(&self) -> &BStr {
+        c_str(&self.application_error_code_category)
+    }
+
+    pub fn bcat_passphrase(&self) -> &BStr {
+        c_str(&self.bcat_passphrase)
+    }
+
+    /// The minimum age required by the organization, `None` if the application isn't rated by it.
+    pub fn rating_age(&self, organization: RatingOrganization) -> Option<u8> {
+        u8::try_from(self.rating_age[organization as usize]).ok()
+    }
+
+    /// Whether communicating with other users is restricted by parental controls.
+    pub fn is_free_communication_restricted(&self) -> bool {
+        self.parental_control_flag & 1 != 0
+    }
+
+    /// Whether selecting a user account on startup can be skipped.
+    pub fn is_startup_user_account_optional(&self) -> bool {
+        self.startup_user_account_option & 1 != 0
+    }
+
+    /// Opens the icon of the given language in the RomFS of the Control NCA.
+    pub fn icon(&self, romfs: &impl FileSystem, language: Language) -> SwonchResult<Storage> {
+        romfs.open_file(language.icon_file_name().as_bytes())
+    }
+
+    /// The icons of every supported language that has one.
+    pub fn icons<'a>(
+        &'a self,
+        romfs: &'a impl FileSystem,
+    ) -> impl Iterator<Item = (Language, Storage)> + 'a {
+        self.supported_languages()
+            .filter_map(|language| Some((language, self.icon(romfs, language).ok()?)))
+    }
+}
+
+impl fmt::Debug for Nacp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Nacp")
+            .field("default_title", &self.default_title())
+            .field("display_version", &self.display_version())
+            .field("supported_language_flag", &self.supported_language_flag)
+            .field("startup_user_account", &self.startup_user_account)
+            .field("save_data_owner_id", &self.save_data_owner_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStorage for Nacp {
+    type Args = ();
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, _: Self::Args) -> Self::Output {
+        Nacp::read(&mut parent.into_stdio()).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        containers::fs::{DirEntry, FsError, Metadata},
+        storage::VecStorage,
+    };
+    use alloc::vec::Vec;
+
+    fn build_nacp() -> Vec<u8> {
+        let mut nacp = vec![0; 0x4000];
+        nacp[0x600..0x606].copy_from_slice(b"Swonch");
+        nacp[0x800..0x809].copy_from_slice(b"dorkeline");
+        nacp[0x3025] = StartupUserAccount::Required as u8;
+        nacp[0x302c] = 0b101;
+        nacp[0x3030] = 1;
+        nacp[0x3040..0x3060].fill(0xff);
+        nacp[0x3043] = 10;
+        nacp[0x3060..0x3065].copy_from_slice(b"1.0.2");
+        nacp[0x3080..0x3088].copy_from_slice(&0x40_0000i64.to_le_bytes());
+        nacp[0x3188..0x318a].copy_from_slice(&3u16.to_le_bytes());
+        nacp
+    }
+
+    // only has the icon of the second supported language
+    struct Icons;
+
+    impl FileSystem for Icons {
+        fn open_file(&self, path: &[u8]) -> SwonchResult<Storage> {
+            match path {
+                b"icon_Japanese.dat" => Ok(VecStorage::new(b"\xff\xd8\xff".to_vec())),
+                _ => Err(FsError::not_found(path).into()),
+            }
+        }
+
+        fn read_dir(&self, path: &[u8]) -> SwonchResult<Vec<DirEntry>> {
+            Err(FsError::not_a_directory(path).into())
+        }
+
+        fn metadata(&self, path: &[u8]) -> SwonchResult<Metadata> {
+            Err(FsError::not_found(path).into())
+        }
+    }
+
+    #[test]
+    fn parse_nacp() -> SwonchResult<()> {
+        let nacp = VecStorage::new(build_nacp()).map_to_storage::<Nacp>(())?;
+
+        assert!(nacp.title(Language::AmericanEnglish).is_none());
+        let title = nacp.default_title().expect("missing title");
+        assert_eq!(title.name(), "Swonch");
+        assert_eq!(title.publisher(), "dorkeline");
+        assert_eq!(
+            nacp.supported_languages().collect::<Vec<_>>(),
+            [Language::AmericanEnglish, Language::Japanese]
+        );
+
+        assert_eq!(nacp.display_version(), "1.0.2");
+        assert_eq!(nacp.startup_user_account, StartupUserAccount::Required);
+        assert!(nacp.is_free_communication_restricted());
+        assert_eq!(nacp.rating_age(RatingOrganization::ESRB), Some(10));
+        assert_eq!(nacp.rating_age(RatingOrganization::PEGI), None);
+        assert_eq!(nacp.user_account_save_data_size, 0x40_0000);
+        assert_eq!(nacp.cache_storage_index_max, 3);
+
+        let icons: Vec<_> = nacp.icons(&Icons).map(|(language, _)| language).collect();
+        assert_eq!(icons, [Language::Japanese]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,575 @@
+//! The program metadata (`main.npdm`) in the ExeFS of Program NCAs. It consists of the `META`
+//! header, the `ACID` signed by Nintendo describing what the program may be granted, and the
+//! `ACI0` describing what the program actually requests.
+
+use crate::{
+    common::ProgramId,
+    storage::{FromStorage, IStorage, Storage},
+    utils::HexArray,
+    SwonchResult,
+};
+use alloc::vec::Vec;
+use binrw::{io::Cursor, BinRead};
+use bstr::{BStr, BString};
+
+use super::c_str;
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum NpdmError {
+    #[error("the {0} reaches past the end of the NPDM")]
+    OutOfBounds(&'static str),
+
+    #[error("memory map kernel capability at index {index} is missing its size")]
+    IncompleteMemoryMap { index: usize },
+}
+
+#[binrw::binrw]
+#[brw(little, magic = b"META")]
+#[derive(Debug, Clone)]
+pub struct MetaHeader {
+    pub signature_key_generation: u32, // 9.0.0+
+    reserved0: u32,
+    pub flags: u8,
+    reserved1: u8,
+    pub main_thread_priority: u8,
+    pub main_thread_core_number: u8,
+    reserved2: u32,
+    pub system_resource_size: u32,
+    pub version: u32,
+    pub main_thread_stack_size: u32,
+    name: [u8; 0x10],
+    product_code: [u8; 0x10],
+    reserved3: HexArray<0x30>,
+    pub aci_offset: u32,
+    pub aci_size: u32,
+    pub acid_offset: u32,
+    pub acid_size: u32,
+}
+
+impl MetaHeader {
+    pub fn name(&self) -> &BStr {
+        c_str(&self.name)
+    }
+
+    pub fn product_code(&self) -> &BStr {
+        c_str(&self.product_code)
+    }
+
+    pub fn is_64bit(&self) -> bool {
+        self.flags & 1 != 0
+    }
+
+    /// 0 for 32 bit, 1 for 64 bit with a 36 bit address space, 2 for 32 bit without a reserved
+    /// region and 3 for 64 bit with a 39 bit address space.
+    pub fn address_space_type(&self) -> u8 {
+        (self.flags >> 1) & 7
+    }
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct AcidHeader {
+    /// RSA-2048 PSS signature over the ACID from the public key on.
+    pub signature: HexArray<0x100>,
+    /// Modulus of the key the [`NcaHeader::npdm_hdr_signature`](crate::containers::nca::NcaHeader) is made with.
+    pub public_key: HexArray<0x100>,
+    #[brw(magic = b"ACID")]
+    pub size: u32,
+    pub version: u8,
+    reserved0: [u8; 3],
+    pub flags: u32,
+    pub program_id_min: ProgramId,
+    pub program_id_max: ProgramId,
+    pub fac_offset: u32,
+    pub fac_size: u32,
+    pub sac_offset: u32,
+    pub sac_size: u32,
+    pub kac_offset: u32,
+    pub kac_size: u32,
+    reserved1: u64,
+}
+
+impl AcidHeader {
+    pub fn is_production(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+#[binrw::binrw]
+#[brw(little, magic = b"ACI0")]
+#[derive(Debug, Clone)]
+pub struct Aci0Header {
+    reserved0: [u8; 0xc],
+    pub program_id: ProgramId,
+    reserved1: u64,
+    pub fah_offset: u32,
+    pub fah_size: u32,
+    pub sac_offset: u32,
+    pub sac_size: u32,
+    pub kac_offset: u32,
+    pub kac_size: u32,
+    reserved2: u64,
+}
+
+/// The filesystem permissions the program may be granted, with the ranges of save data and
+/// content owners it may access.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct FsAccessControl {
+    pub version: u8,
+    content_owner_id_count: u8,
+    save_data_owner_id_count: u8,
+    reserved: u8,
+    pub flags: u64,
+    pub content_owner_id_min: u64,
+    pub content_owner_id_max: u64,
+    pub save_data_owner_id_min: u64,
+    pub save_data_owner_id_max: u64,
+    #[br(count = content_owner_id_count)]
+    pub content_owner_ids: Vec<u64>,
+    #[br(count = save_data_owner_id_count)]
+    pub save_data_owner_ids: Vec<u64>,
+}
+
+/// The filesystem permissions the program requests.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct FsAccessHeader {
+    pub version: u8,
+    reserved: [u8; 3],
+    pub flags: u64,
+    pub content_owner_info_offset: u32,
+    pub content_owner_info_size: u32,
+    pub save_data_owner_info_offset: u32,
+    pub save_data_owner_info_size: u32,
+}
+
+/// A service the program may use, or host if it's a server. Names may end in a `*` wildcard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceAccess {
+    pub name: BString,
+    pub is_server: bool,
+}
+
+impl ServiceAccess {
+    fn parse_list(mut raw: &[u8]) -> Vec<Self> {
+        let mut services = vec![];
+
+        // every name is prefixed by its length - 1 in the lower bits, the rest is padding
+        while let [control, rest @ ..] = raw {
+            if *control == 0 {
+                break;
+            }
+
+            let len = (control & 7) as usize + 1;
+            let Some(name) = rest.get(..len) else {
+                break;
+            };
+
+            services.push(Self {
+                name: name.into(),
+                is_server: control & 0x80 != 0,
+            });
+            raw = &rest[len..];
+        }
+
+        services
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ApplicationType {
+    System = 0,
+    Application = 1,
+    Applet = 2,
+}
+
+/// A single decoded kernel capability descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelCapability {
+    /// Priorities are inverted, the lowest priority has the highest value.
+    ThreadInfo {
+        lowest_priority: u8,
+        highest_priority: u8,
+        min_core: u8,
+        max_core: u8,
+    },
+    /// 24 system calls starting at `index * 24`, one bit each.
+    EnableSystemCalls {
+        index: u8,
+        mask: u32,
+    },
+    MemoryMap {
+        address: u64,
+        size: u64,
+        is_read_only: bool,
+        is_io: bool,
+    },
+    IoMemoryMap {
+        address: u64,
+    },
+    EnableInterrupts([Option<u16>; 2]),
+    MiscParams {
+        /// `None` for unknown values.
+        application_type: Option<ApplicationType>,
+    },
+    KernelVersion {
+        major: u16,
+        minor: u8,
+    },
+    HandleTableSize(u16),
+    DebugFlags {
+        allow_debug: bool,
+        force_debug: bool,
+    },
+    /// Anything else, e.g. the memory region maps of 13.0.0+.
+    Unknown(u32),
+}
+
+/// Extracts `len` bits starting at bit `start`.
+fn bits(value: u32, start: u32, len: u32) -> u32 {
+    (value >> start) & ((1 << len) - 1)
+}
+
+/// The kernel capabilities of an ACID or ACI0.
+#[derive(Debug, Clone, Default)]
+pub struct KernelCapabilities(pub Vec<KernelCapability>);
+
+impl KernelCapabilities {
+    fn parse(raw: &[u8]) -> Result<Self, NpdmError> {
+        let mut descriptors = raw
+            .chunks_exact(4)
+            .map(|d| u32::from_le_bytes([d[0], d[1], d[2], d[3]]))
+            .enumerate();
+        let mut caps = vec![];
+
+        // the type of a descriptor is given by the number of trailing set bits
+        while let Some((index, desc)) = descriptors.next() {
+            caps.push(match desc.trailing_ones() {
+                3 => KernelCapability::ThreadInfo {
+                    lowest_priority: bits(desc, 4, 6) as u8,
+                    highest_priority: bits(desc, 10, 6) as u8,
+                    min_core: bits(desc, 16, 8) as u8,
+                    max_core: bits(desc, 24, 8) as u8,
+                },
+                4 => KernelCapability::EnableSystemCalls {
+                    index: bits(desc, 29, 3) as u8,
+                    mask: bits(desc, 5, 24),
+                },
+                6 => {
+                    let (_, size) = descriptors
+                        .next()
+                        .filter(|(_, size)| size.trailing_ones() == 6)
+                        .ok_or(NpdmError::IncompleteMemoryMap { index })?;
+
+                    KernelCapability::MemoryMap {
+                        address: (bits(desc, 7, 24) as u64) << 12,
+                        size: (bits(size, 7, 20) as u64) << 12,
+                        is_read_only: desc & (1 << 31) != 0,
+                        is_io: size & (1 << 31) == 0,
+                    }
+                }
+                7 => KernelCapability::IoMemoryMap {
+                    address: (bits(desc, 8, 24) as u64) << 12,
+                },
+                11 => KernelCapability::EnableInterrupts(
+                    [bits(desc, 12, 10), bits(desc, 22, 10)]
+                        .map(|irq| (irq != 0x3ff).then_some(irq as u16)),
+                ),
+                13 => KernelCapability::MiscParams {
+                    application_type: match bits(desc, 14, 3) {
+                        0 => Some(ApplicationType::System),
+                        1 => Some(ApplicationType::Application),
+                        2 => Some(ApplicationType::Applet),
+                        _ => None,
+                    },
+                },
+                14 => KernelCapability::KernelVersion {
+                    major: bits(desc, 19, 13) as u16,
+                    minor: bits(desc, 15, 4) as u8,
+                },
+                15 => KernelCapability::HandleTableSize(bits(desc, 16, 10) as u16),
+                16 => KernelCapability::DebugFlags {
+                    allow_debug: desc & (1 << 17) != 0,
+                    force_debug: desc & (1 << 18) != 0,
+                },
+                // unused descriptor
+                32 => continue,
+                _ => KernelCapability::Unknown(desc),
+            });
+        }
+
+        Ok(Self(caps))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KernelCapability> {
+        self.0.iter()
+    }
+
+    /// The IDs of every allowed system call, in ascending order.
+    pub fn syscalls(&self) -> Vec<u32> {
+        let mut syscalls: Vec<u32> = self
+            .iter()
+            .filter_map(|cap| match cap {
+                KernelCapability::EnableSystemCalls { index, mask } => Some((*index, *mask)),
+                _ => None,
+            })
+            .flat_map(|(index, mask)| {
+                (0..24)
+                    .filter(move |bit| mask & (1 << bit) != 0)
+                    .map(move |bit| index as u32 * 24 + bit)
+            })
+            .collect();
+
+        syscalls.sort_unstable();
+        syscalls.dedup();
+        syscalls
+    }
+
+    /// The IDs of every allowed interrupt.
+    pub fn interrupts(&self) -> impl Iterator<Item = u16> + '_ {
+        self.iter()
+            .filter_map(|cap| match cap {
+                KernelCapability::EnableInterrupts(irqs) => Some(*irqs),
+                _ => None,
+            })
+            .flatten()
+            .flatten()
+    }
+
+    pub fn application_type(&self) -> Option<ApplicationType> {
+        self.iter().find_map(|cap| match cap {
+            KernelCapability::MiscParams { application_type } => *application_type,
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Acid {
+    pub header: AcidHeader,
+    pub fs_access: FsAccessControl,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: KernelCapabilities,
+}
+
+#[derive(Debug, Clone)]
+pub struct Aci0 {
+    pub header: Aci0Header,
+    pub fs_access: FsAccessHeader,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: KernelCapabilities,
+}
+
+#[derive(Debug, Clone)]
+pub struct Npdm {
+    meta: MetaHeader,
+    acid: Acid,
+    aci0: Aci0,
+}
+
+impl Npdm {
+    pub fn meta(&self) -> &MetaHeader {
+        &self.meta
+    }
+
+    pub fn acid(&self) -> &Acid {
+        &self.acid
+    }
+
+    pub fn aci0(&self) -> &Aci0 {
+        &self.aci0
+    }
+}
+
+/// The `size` bytes at `offset` into `buf`.
+fn region<'a>(
+    buf: &'a [u8],
+    offset: u32,
+    size: u32,
+    what: &'static str,
+) -> Result<&'a [u8], NpdmError> {
+    buf.get(offset as usize..)
+        .and_then(|buf| buf.get(..size as usize))
+        .ok_or(NpdmError::OutOfBounds(what))
+}
+
+impl FromStorage for Npdm {
+    type Args = ();
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, _: Self::Args) -> Self::Output {
+        let mut buf = vec![0; parent.length()? as usize];
+        parent.read_at(0, &mut buf)?;
+
+        let meta = MetaHeader::read(&mut Cursor::new(&buf))?;
+
+        let raw = region(&buf, meta.acid_offset, meta.acid_size, "ACID")?;
+        let header = AcidHeader::read(&mut Cursor::new(raw))?;
+        let acid = Acid {
+            fs_access: FsAccessControl::read(&mut Cursor::new(region(
+                raw,
+                header.fac_offset,
+                header.fac_size,
+                "ACID FS access control",
+            )?))?,
+            services: ServiceAccess::parse_list(region(
+                raw,
+                header.sac_offset,
+                header.sac_size,
+                "ACID service access control",
+            )?),
+            kernel_capabilities: KernelCapabilities::parse(region(
+                raw,
+                header.kac_offset,
+                header.kac_size,
+                "ACID kernel capabilities",
+            )?)?,
+            header,
+        };
+
+        let raw = region(&buf, meta.aci_offset, meta.aci_size, "ACI0")?;
+        let header = Aci0Header::read(&mut Cursor::new(raw))?;
+        let aci0 = Aci0 {
+            fs_access: FsAccessHeader::read(&mut Cursor::new(region(
+                raw,
+                header.fah_offset,
+                header.fah_size,
+                "ACI0 FS access header",
+            )?))?,
+            services: ServiceAccess::parse_list(region(
+                raw,
+                header.sac_offset,
+                header.sac_size,
+                "ACI0 service access control",
+            )?),
+            kernel_capabilities: KernelCapabilities::parse(region(
+                raw,
+                header.kac_offset,
+                header.kac_size,
+                "ACI0 kernel capabilities",
+            )?)?,
+            header,
+        };
+
+        Ok(Self { meta, acid, aci0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::VecStorage;
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    // ACID and ACI0 share the services and kernel capabilities
+    fn build_npdm() -> Vec<u8> {
+        let services = [b"\x82sm:".as_slice(), b"\x06fsp-srv", b"\x00"].concat();
+        let kac = u32s(&[
+            // priorities 44..=59 on core 0..=2
+            0b0111 | 59 << 4 | 44 << 10 | 2 << 24,
+            // svc 0x19 and 0x1f
+            0b1111 | 1 << 6 | 1 << (5 + 7) | 1 << 29,
+            // read only io mapping of 0x3000 bytes at 0x5000_0000
+            0b11_1111 | 0x5_0000 << 7 | 1 << 31,
+            0b11_1111 | 3 << 7,
+            // interrupt 0x20, the second slot is unused
+            0b111_1111_1111 | 0x20 << 12 | 0x3ff << 22,
+            // application
+            0b1_1111_1111_1111 | 1 << 14,
+            u32::MAX,
+        ]);
+
+        let mut fac = vec![1, 0, 0, 0];
+        fac.extend(0x8000_0000_0000_0001u64.to_le_bytes());
+        fac.extend([0; 0x20]);
+
+        let mut acid = vec![0; 0x240];
+        acid[0x100..0x104].copy_from_slice(b"\xaa\xbb\xcc\xdd");
+        acid[0x200..0x204].copy_from_slice(b"ACID");
+        let mut offset = 0x240u32;
+        for (idx, part) in [&fac, &services, &kac].iter().enumerate() {
+            acid[0x220 + idx * 8..][..8].copy_from_slice(&u32s(&[offset, part.len() as u32]));
+            offset += part.len() as u32;
+        }
+        acid.extend([fac.as_slice(), &services, &kac].concat());
+
+        let mut aci0 = vec![0; 0x40];
+        aci0[..4].copy_from_slice(b"ACI0");
+        aci0[0x10..0x18].copy_from_slice(&0x0100_0000_0000_1000u64.to_le_bytes());
+        let fah = [vec![1, 0, 0, 0], 1u64.to_le_bytes().into(), vec![0; 0x10]].concat();
+        let mut offset = 0x40u32;
+        for (idx, part) in [&fah, &services, &kac].iter().enumerate() {
+            aci0[0x20 + idx * 8..][..8].copy_from_slice(&u32s(&[offset, part.len() as u32]));
+            offset += part.len() as u32;
+        }
+        aci0.extend([fah.as_slice(), &services, &kac].concat());
+
+        let mut npdm = vec![0; 0x80];
+        npdm[..4].copy_from_slice(b"META");
+        npdm[0xc] = 0b111;
+        npdm[0x20..0x26].copy_from_slice(b"swonch");
+        let aci_offset = 0x80 + acid.len() as u32;
+        npdm[0x70..0x80].copy_from_slice(&u32s(&[
+            aci_offset,
+            aci0.len() as u32,
+            0x80,
+            acid.len() as u32,
+        ]));
+        npdm.extend(acid);
+        npdm.extend(aci0);
+        npdm
+    }
+
+    #[test]
+    fn parse_npdm() -> SwonchResult<()> {
+        let npdm = VecStorage::new(build_npdm()).map_to_storage::<Npdm>(())?;
+
+        assert_eq!(npdm.meta().name(), "swonch");
+        assert!(npdm.meta().is_64bit());
+        assert_eq!(npdm.meta().address_space_type(), 3);
+        assert_eq!(npdm.acid().header.public_key.0[..4], *b"\xaa\xbb\xcc\xdd");
+        assert_eq!(npdm.acid().fs_access.flags, 0x8000_0000_0000_0001);
+        assert_eq!(npdm.aci0().fs_access.flags, 1);
+
+        let services = &npdm.aci0().services;
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].name, "sm:");
+        assert!(services[0].is_server);
+        assert_eq!(services[1].name, "fsp-srv");
+        assert!(!services[1].is_server);
+
+        let caps = &npdm.acid().kernel_capabilities;
+        assert_eq!(
+            caps.0[0],
+            KernelCapability::ThreadInfo {
+                lowest_priority: 59,
+                highest_priority: 44,
+                min_core: 0,
+                max_core: 2
+            }
+        );
+        assert_eq!(caps.syscalls(), [0x19, 0x1f]);
+        assert_eq!(
+            caps.0[2],
+            KernelCapability::MemoryMap {
+                address: 0x5000_0000,
+                size: 0x3000,
+                is_read_only: true,
+                is_io: true
+            }
+        );
+        assert_eq!(caps.interrupts().collect::<Vec<_>>(), [0x20]);
+        assert_eq!(caps.application_type(), Some(ApplicationType::Application));
+        assert_eq!(caps.0.len(), 5);
+
+        Ok(())
+    }
+}
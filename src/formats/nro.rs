@@ -0,0 +1,279 @@
+//! The relocatable executables used by homebrew, optionally followed by assets (an icon, a NACP
+//! and a RomFS).
+
+use crate::{
+    containers::romfs::RomFs,
+    storage::{FromStorage, IStorage, Storage},
+    utils::HexArray,
+    SwonchResult,
+};
+use binrw::BinRead;
+
+use super::{
+    nacp::Nacp,
+    nso::{RoRegion, SegmentKind},
+};
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct NroSegmentHeader {
+    /// Offset of the segment from the start of the file, which is also the start of the loaded module.
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone)]
+pub struct NroHeader {
+    reserved0: u32,
+    pub mod0_offset: u32,
+    reserved1: u64,
+    #[brw(magic = b"NRO0")]
+    pub version: u32,
+    /// Size of the NRO without the assets.
+    pub size: u32,
+    pub flags: u32,
+    pub text: NroSegmentHeader,
+    pub ro: NroSegmentHeader,
+    pub data: NroSegmentHeader,
+    pub bss_size: u32,
+    reserved2: u32,
+    /// The GNU build ID of the module, padded with zeros.
+    pub module_id: HexArray<0x20>,
+    pub dso_handle_offset: u32,
+    reserved3: u32,
+    pub api_info: RoRegion,
+    pub dynstr: RoRegion,
+    pub dynsym: RoRegion,
+}
+
+impl NroHeader {
+    pub fn segment(&self, kind: SegmentKind) -> &NroSegmentHeader {
+        match kind {
+            SegmentKind::Text => &self.text,
+            SegmentKind::Ro => &self.ro,
+            SegmentKind::Data => &self.data,
+        }
+    }
+}
+
+/// A range of the assets, relative to the start of the [`AssetHeader`].
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct AssetSection {
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[binrw::binrw]
+#[brw(little, magic = b"ASET")]
+#[derive(Debug, Clone)]
+pub struct AssetHeader {
+    pub version: u32,
+    pub icon: AssetSection,
+    pub nacp: AssetSection,
+    pub romfs: AssetSection,
+}
+
+#[derive(Debug)]
+pub struct Nro {
+    storage: Storage,
+    header: NroHeader,
+    assets: Option<AssetHeader>,
+}
+
+impl Nro {
+    pub fn header(&self) -> &NroHeader {
+        &self.header
+    }
+
+    /// The header of the assets, `None` if the NRO has none.
+    pub fn asset_header(&self) -> Option<&AssetHeader> {
+        self.assets.as_ref()
+    }
+
+    pub fn module_id(&self) -> &HexArray<0x20> {
+        &self.header.module_id
+    }
+
+    pub fn bss_size(&self) -> u32 {
+        self.header.bss_size
+    }
+
+    /// Size of the loaded module, from the start of text to the end of bss.
+    pub fn image_size(&self) -> u64 {
+        let data = &self.header.data;
+        data.offset as u64 + data.size as u64 + self.header.bss_size as u64
+    }
+
+    /// The segment an offset from the start of the loaded module falls into.
+    pub fn segment_containing(&self, offset: u64) -> Option<SegmentKind> {
+        SegmentKind::ALL.into_iter().find(|kind| {
+            let segment = self.header.segment(*kind);
+            (segment.offset as u64..segment.offset as u64 + segment.size as u64).contains(&offset)
+        })
+    }
+
+    /// Segments of NROs are stored uncompressed, at their offset in memory.
+    pub fn segment(&self, kind: SegmentKind) -> SwonchResult<Storage> {
+        let segment = self.header.segment(kind);
+        self.storage
+            .clone()
+            .split(segment.offset as u64, segment.size as u64)
+    }
+
+    /// The dynamic string table, from the rodata segment.
+    pub fn dynstr(&self) -> SwonchResult<Storage> {
+        let region = self.header.dynstr;
+        self.segment(SegmentKind::Ro)?
+            .split(region.offset as u64, region.size as u64)
+    }
+
+    /// The dynamic symbol table, from the rodata segment.
+    pub fn dynsym(&self) -> SwonchResult<Storage> {
+        let region = self.header.dynsym;
+        self.segment(SegmentKind::Ro)?
+            .split(region.offset as u64, region.size as u64)
+    }
+
+    fn asset(
+        &self,
+        section: impl Fn(&AssetHeader) -> AssetSection,
+    ) -> SwonchResult<Option<Storage>> {
+        let Some(assets) = &self.assets else {
+            return Ok(None);
+        };
+
+        let section = section(assets);
+        if section.size == 0 {
+            return Ok(None);
+        }
+
+        self.storage
+            .clone()
+            .split(self.header.size as u64 + section.offset, section.size)
+            .map(Some)
+    }
+
+    /// The JPEG icon, `None` if the NRO has none.
+    pub fn icon(&self) -> SwonchResult<Option<Storage>> {
+        self.asset(|assets| assets.icon)
+    }
+
+    /// The NACP, `None` if the NRO has none.
+    pub fn nacp(&self) -> SwonchResult<Option<Nacp>> {
+        self.asset(|assets| assets.nacp)?
+            .map(|nacp| nacp.map_to_storage::<Nacp>(()))
+            .transpose()
+    }
+
+    /// The RomFS, `None` if the NRO has none.
+    pub fn romfs(&self) -> SwonchResult<Option<RomFs>> {
+        self.asset(|assets| assets.romfs)?
+            .map(|romfs| romfs.map_to_storage::<RomFs>(()))
+            .transpose()
+    }
+}
+
+impl FromStorage for Nro {
+    type Args = ();
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, _: Self::Args) -> Self::Output {
+        let header = NroHeader::read(&mut parent.clone().into_stdio())?;
+
+        // the assets are appended to the end of the NRO
+        let mut magic = [0; 4];
+        let read = parent.read_at(header.size as u64, &mut magic)?;
+        let assets = match &magic[..read as usize] {
+            b"ASET" => {
+                let assets_len = parent.length()? - header.size as u64;
+                let assets = parent.clone().split(header.size as u64, assets_len)?;
+                Some(AssetHeader::read(&mut assets.into_stdio())?)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            storage: parent,
+            header,
+            assets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{containers::romfs::tests::build_romfs, storage::VecStorage};
+    use alloc::vec::Vec;
+
+    fn build_nro() -> SwonchResult<Vec<u8>> {
+        let mut nro = vec![0; 0x80];
+        nro[0x10..0x14].copy_from_slice(b"NRO0");
+        nro[0x18..0x1c].copy_from_slice(&0x300u32.to_le_bytes());
+        for (idx, (offset, size)) in [(0u32, 0x100u32), (0x100, 0x100), (0x200, 0x100)]
+            .into_iter()
+            .enumerate()
+        {
+            nro[0x20 + idx * 8..][..4].copy_from_slice(&offset.to_le_bytes());
+            nro[0x24 + idx * 8..][..4].copy_from_slice(&size.to_le_bytes());
+        }
+        nro[0x38..0x3c].copy_from_slice(&0x1000u32.to_le_bytes());
+        nro[0x40..0x54].fill(0xb1);
+        // dynstr
+        nro[0x70..0x74].copy_from_slice(&0x10u32.to_le_bytes());
+        nro[0x74..0x78].copy_from_slice(&6u32.to_le_bytes());
+        nro.resize(0x300, 0);
+        nro[0x110..0x116].copy_from_slice(b"_init\0");
+
+        let mut nacp = vec![0; 0x4000];
+        nacp[..3].copy_from_slice(b"hbl");
+        nacp[0x302c] = 1;
+        let romfs = build_romfs(0x50)?;
+
+        nro.extend(b"ASET");
+        nro.extend(0u32.to_le_bytes());
+        let mut offset = 0x38u64;
+        for size in [0, nacp.len(), romfs.len()] {
+            nro.extend(offset.to_le_bytes());
+            nro.extend((size as u64).to_le_bytes());
+            offset += size as u64;
+        }
+        nro.extend(nacp);
+        nro.extend(romfs);
+        Ok(nro)
+    }
+
+    #[test]
+    fn parse_nro() -> SwonchResult<()> {
+        let nro = VecStorage::new(build_nro()?).map_to_storage::<Nro>(())?;
+
+        assert_eq!(&nro.module_id().0[..0x14], &[0xb1; 0x14]);
+        assert_eq!(nro.image_size(), 0x1300);
+        assert_eq!(nro.segment_containing(0x180), Some(SegmentKind::Ro));
+        assert_eq!(nro.segment(SegmentKind::Data)?.length()?, 0x100);
+
+        let mut buf = [0; 6];
+        nro.dynstr()?.read_at(0, &mut buf)?;
+        assert_eq!(&buf, b"_init\0");
+
+        assert!(nro.icon()?.is_none());
+        let nacp = nro.nacp()?.expect("missing NACP");
+        assert_eq!(nacp.default_title().map(|t| t.name()), Some("hbl".into()));
+
+        let romfs = nro.romfs()?.expect("missing RomFS");
+        let mut buf = [0; 4];
+        romfs
+            .open_file("/data/bar.bin")
+            .expect("bar.bin not found")
+            .data()?
+            .read_at(0, &mut buf)?;
+        assert_eq!(&buf, b"bar!");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+//! The executables (`main`, `rtld`, `sdk`, `subsdk*`) in the ExeFS of Program NCAs.
+
+use crate::{
+    storage::{compressed::MAX_LZ4_RATIO, FromStorage, IStorage, Storage, VecStorage},
+    utils::HexArray,
+    Integrity, SwonchResult,
+};
+use binrw::BinRead;
+use bstr::BString;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    Text,
+    Ro,
+    Data,
+}
+
+impl SegmentKind {
+    pub const ALL: [SegmentKind; 3] = [SegmentKind::Text, SegmentKind::Ro, SegmentKind::Data];
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentHeader {
+    pub file_offset: u32,
+    /// Offset of the segment from the start of the loaded module.
+    pub memory_offset: u32,
+    /// Decompressed size.
+    pub size: u32,
+}
+
+/// A range inside of the rodata segment.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct RoRegion {
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[binrw::binrw]
+#[brw(little, magic = b"NSO0")]
+#[derive(Debug, Clone)]
+pub struct NsoHeader {
+    pub version: u32,
+    reserved0: u32,
+    pub flags: u32,
+    pub text: SegmentHeader,
+    pub module_name_offset: u32,
+    pub ro: SegmentHeader,
+    pub module_name_size: u32,
+    pub data: SegmentHeader,
+    pub bss_size: u32,
+    /// The GNU build ID of the module, padded with zeros.
+    pub module_id: HexArray<0x20>,
+    pub text_file_size: u32,
+    pub ro_file_size: u32,
+    pub data_file_size: u32,
+    reserved1: [u8; 0x1c],
+    pub api_info: RoRegion,
+    pub dynstr: RoRegion,
+    pub dynsym: RoRegion,
+    pub text_hash: HexArray<0x20>,
+    pub ro_hash: HexArray<0x20>,
+    pub data_hash: HexArray<0x20>,
+}
+
+impl NsoHeader {
+    pub fn segment(&self, kind: SegmentKind) -> &SegmentHeader {
+        match kind {
+            SegmentKind::Text => &self.text,
+            SegmentKind::Ro => &self.ro,
+            SegmentKind::Data => &self.data,
+        }
+    }
+
+    /// Size of the segment in the file, which differs from the actual size if it's compressed.
+    pub fn file_size(&self, kind: SegmentKind) -> u32 {
+        match kind {
+            SegmentKind::Text => self.text_file_size,
+            SegmentKind::Ro => self.ro_file_size,
+            SegmentKind::Data => self.data_file_size,
+        }
+    }
+
+    /// SHA-256 over the decompressed segment.
+    pub fn hash(&self, kind: SegmentKind) -> &HexArray<0x20> {
+        match kind {
+            SegmentKind::Text => &self.text_hash,
+            SegmentKind::Ro => &self.ro_hash,
+            SegmentKind::Data => &self.data_hash,
+        }
+    }
+
+    pub fn is_compressed(&self, kind: SegmentKind) -> bool {
+        self.flags & (1 << kind as u32) != 0
+    }
+
+    /// Whether the loader checks the hash of the segment.
+    pub fn is_hash_checked(&self, kind: SegmentKind) -> bool {
+        self.flags & (1 << (kind as u32 + 3)) != 0
+    }
+}
+
+#[derive(Debug)]
+pub struct Nso {
+    storage: Storage,
+    header: NsoHeader,
+    integrity: Integrity,
+}
+
+impl Nso {
+    pub fn header(&self) -> &NsoHeader {
+        &self.header
+    }
+
+    pub fn module_id(&self) -> &HexArray<0x20> {
+        &self.header.module_id
+    }
+
+    pub fn bss_size(&self) -> u32 {
+        self.header.bss_size
+    }
+
+    /// Size of the loaded module, from the start of text to the end of bss.
+    pub fn image_size(&self) -> u64 {
+        let data = &self.header.data;
+        data.memory_offset as u64 + data.size as u64 + self.header.bss_size as u64
+    }
+
+    /// The segment an offset from the start of the loaded module falls into.
+    pub fn segment_containing(&self, offset: u64) -> Option<SegmentKind> {
+        SegmentKind::ALL.into_iter().find(|kind| {
+            let segment = self.header.segment(*kind);
+            (segment.memory_offset as u64..segment.memory_offset as u64 + segment.size as u64)
+                .contains(&offset)
+        })
+    }
+
+    /// The name of the module, usually the path of the object it was built from.
+    pub fn module_name(&self) -> SwonchResult<BString> {
+        let name_end = self.header.module_name_offset as u64 + self.header.module_name_size as u64;
+        if name_end > self.storage.length()? {
+            return Err(NsoError::ModuleNameOutOfBounds.into());
+        }
+
+        let mut name = vec![0; self.header.module_name_size as usize];
+        self.storage
+            .read_at(self.header.module_name_offset as u64, &mut name)?;
+
+        Ok(super::c_str(&name).to_owned())
+    }
+
+    /// Reads the decompressed segment. If the header asks for it, the segment is checked against
+    /// its hash according to the [`Integrity`] the NSO was opened with.
+    pub fn segment(&self, kind: SegmentKind) -> SwonchResult<Storage> {
+        let segment = self.header.segment(kind);
+        let file_size = self.header.file_size(kind) as u64;
+        let compressed = self.header.is_compressed(kind);
+
+        // check the sizes before allocating anything based on them
+        let file_end = segment.file_offset as u64 + file_size;
+        if file_end > self.storage.length()?
+            || (compressed && segment.size as u64 > file_size * MAX_LZ4_RATIO)
+        {
+            return Err(NsoError::SegmentOutOfBounds(kind).into());
+        }
+
+        let mut raw = vec![0; file_size as usize];
+        self.storage.read_at(segment.file_offset as u64, &mut raw)?;
+
+        let data = match compressed {
+            true => {
+                let mut data = vec![0; segment.size as usize];
+                let len = lz4_flex::block::decompress_into(&raw, &mut data)
+                    .map_err(|_| NsoError::DecompressionFailed(kind))?;
+                if len != data.len() {
+                    return Err(NsoError::DecompressionFailed(kind).into());
+                }
+                data
+            }
+            false => raw,
+        };
+
+        if self.header.is_hash_checked(kind) {
+            let hash_in_header = self.header.hash(kind);
+            if let Err(hash) = crate::utils::validate_hash::<Sha256>(&data, &hash_in_header.0) {
+                match self.integrity {
+                    Integrity::WarnOnly => log::error!(
+                        "{kind:?} segment hash mismatch. NSO header claims {hash_in_header:?} but actual hash is {}",
+                        HexArray::<0x20>(hash.into()),
+                    ),
+                    Integrity::ErrorOnMismatch => {
+                        return Err(NsoError::SegmentHashMismatch {
+                            segment: kind,
+                            hash_in_header: hash_in_header.0,
+                            actual_hash: hash.into(),
+                        }
+                        .into())
+                    }
+                }
+            }
+        }
+
+        Ok(VecStorage::new(data))
+    }
+
+    /// The dynamic string table, from the rodata segment.
+    pub fn dynstr(&self) -> SwonchResult<Storage> {
+        let region = self.header.dynstr;
+        self.segment(SegmentKind::Ro)?
+            .split(region.offset as u64, region.size as u64)
+    }
+
+    /// The dynamic symbol table, from the rodata segment.
+    pub fn dynsym(&self) -> SwonchResult<Storage> {
+        let region = self.header.dynsym;
+        self.segment(SegmentKind::Ro)?
+            .split(region.offset as u64, region.size as u64)
+    }
+}
+
+impl FromStorage for Nso {
+    type Args = Integrity;
+    type Output = SwonchResult<Self>;
+
+    fn from_storage(parent: Storage, integrity: Self::Args) -> Self::Output {
+        let header = NsoHeader::read(&mut parent.clone().into_stdio())?;
+
+        Ok(Self {
+            storage: parent,
+            header,
+            integrity,
+        })
+    }
+}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum NsoError {
+    #[error("failed to decompress the {0:?} segment")]
+    DecompressionFailed(SegmentKind),
+
+    #[error("the {0:?} segment is out of bounds")]
+    SegmentOutOfBounds(SegmentKind),
+
+    #[error("the module name is out of bounds")]
+    ModuleNameOutOfBounds,
+
+    #[error("hash mismatch on the {segment:?} segment")]
+    SegmentHashMismatch {
+        segment: SegmentKind,
+        hash_in_header: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SwonchError;
+    use alloc::vec::Vec;
+    use sha2::Digest;
+
+    const TEXT: [u8; 0x100] = [0x1f; 0x100];
+    const RO: &[u8] = b"\0main\0nn::Result\0";
+    const DATA: [u8; 0x40] = [0xdd; 0x40];
+
+    fn build_nso(text_hash: [u8; 0x20]) -> Vec<u8> {
+        let text = lz4_flex::block::compress(&TEXT);
+        let data = lz4_flex::block::compress(&DATA);
+        let name = b"main.elf\0";
+
+        let text_offset = 0x100;
+        let ro_offset = text_offset + text.len() as u32;
+        let data_offset = ro_offset + RO.len() as u32;
+        let name_offset = data_offset + data.len() as u32;
+
+        let mut nso = vec![];
+        nso.extend(b"NSO0");
+        nso.extend(0u32.to_le_bytes());
+        nso.extend(0u32.to_le_bytes());
+        // text and data are compressed, only text is hash checked
+        nso.extend(0b1101u32.to_le_bytes());
+        for (file_offset, memory_offset, size, extra) in [
+            (text_offset, 0, TEXT.len(), name_offset),
+            (ro_offset, 0x1000, RO.len(), name.len() as u32),
+            (data_offset, 0x2000, DATA.len(), 0x80),
+        ] {
+            nso.extend(file_offset.to_le_bytes());
+            nso.extend((memory_offset as u32).to_le_bytes());
+            nso.extend((size as u32).to_le_bytes());
+            nso.extend(extra.to_le_bytes());
+        }
+        nso.extend([0xb1; 0x14]);
+        nso.extend([0; 0xc]);
+        for size in [text.len(), RO.len(), data.len()] {
+            nso.extend((size as u32).to_le_bytes());
+        }
+        nso.extend([0; 0x1c]);
+        // api info, dynstr and dynsym
+        for value in [0u32, 0, 1, 0x10, 0, 0] {
+            nso.extend(value.to_le_bytes());
+        }
+        nso.extend(text_hash);
+        nso.extend([0; 0x40]);
+        assert_eq!(nso.len(), 0x100);
+
+        nso.extend(text);
+        nso.extend(RO);
+        nso.extend(data);
+        nso.extend(name);
+        nso
+    }
+
+    #[test]
+    fn parse_nso() -> SwonchResult<()> {
+        let storage = VecStorage::new(build_nso(Sha256::digest(TEXT).into()));
+        let nso = storage.map_to_storage::<Nso>(Integrity::ErrorOnMismatch)?;
+
+        assert_eq!(&nso.module_id().0[..0x14], &[0xb1; 0x14]);
+        assert_eq!(nso.module_name()?, "main.elf");
+        assert_eq!(nso.bss_size(), 0x80);
+        assert_eq!(nso.image_size(), 0x20c0);
+        assert_eq!(nso.segment_containing(0x1005), Some(SegmentKind::Ro));
+        assert_eq!(nso.segment_containing(0x1800), None);
+
+        for (kind, expected) in [
+            (SegmentKind::Text, &TEXT[..]),
+            (SegmentKind::Ro, RO),
+            (SegmentKind::Data, &DATA[..]),
+        ] {
+            let segment = nso.segment(kind)?;
+            let mut buf = vec![0; segment.length()? as usize];
+            segment.read_at(0, &mut buf)?;
+            assert_eq!(buf, expected);
+        }
+
+        let dynstr = nso.dynstr()?;
+        let mut buf = vec![0; dynstr.length()? as usize];
+        dynstr.read_at(0, &mut buf)?;
+        assert_eq!(buf, b"main\0nn::Result\0");
+
+        let corrupted = VecStorage::new(build_nso([0; 0x20]))
+            .map_to_storage::<Nso>(Integrity::ErrorOnMismatch)?;
+        assert!(corrupted.segment(SegmentKind::Text).is_err());
+        assert!(corrupted.segment(SegmentKind::Data).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_bad_segment_sizes() -> SwonchResult<()> {
+        let nso = build_nso(Sha256::digest(TEXT).into());
+        let open = |nso: Vec<u8>| {
+            VecStorage::new(nso)
+                .map_to_storage::<Nso>(Integrity::ErrorOnMismatch)?
+                .segment(SegmentKind::Text)
+        };
+
+        // decompresses to less than the size in the header
+        let mut short = nso.clone();
+        short[0x18..0x1c].copy_from_slice(&0x200u32.to_le_bytes());
+        assert!(matches!(
+            open(short),
+            Err(SwonchError::Nso(NsoError::DecompressionFailed(
+                SegmentKind::Text
+            )))
+        ));
+
+        // extends past the end of the file
+        let mut truncated = nso.clone();
+        truncated[0x60..0x64].copy_from_slice(&0x10_0000u32.to_le_bytes());
+        assert!(matches!(
+            open(truncated),
+            Err(SwonchError::Nso(NsoError::SegmentOutOfBounds(
+                SegmentKind::Text
+            )))
+        ));
+
+        // more than LZ4 could ever decompress to
+        let mut huge = nso.clone();
+        huge[0x18..0x1c].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            open(huge),
+            Err(SwonchError::Nso(NsoError::SegmentOutOfBounds(
+                SegmentKind::Text
+            )))
+        ));
+
+        // a module name size that isn't allocated
+        let mut huge_name = nso;
+        huge_name[0x2c..0x30].copy_from_slice(&u32::MAX.to_le_bytes());
+        let nso = VecStorage::new(huge_name).map_to_storage::<Nso>(Integrity::ErrorOnMismatch)?;
+        assert!(matches!(
+            nso.module_name(),
+            Err(SwonchError::Nso(NsoError::ModuleNameOutOfBounds))
+        ));
+
+        Ok(())
+    }
+}
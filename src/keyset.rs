@@ -107,6 +107,36 @@ impl FromRawKey for Aes128XtsKey {
     }
 }
 
+/// The modulus of an RSA-2048 public key, the exponent is always 65537.
+#[derive(Debug, Clone)]
+pub struct Rsa2048Modulus(pub [u8; 0x100]);
+
+impl FromRawKey for Rsa2048Modulus {
+    fn from_key(key: &[u8]) -> Result<Self, KeyError> {
+        key.try_into().map(Self).map_err(|_| {
+            crate::utils::ParseKeyError::LengthMismatch {
+                requested_key_len: 0x100,
+                actual_key_len: key.len(),
+            }
+            .into()
+        })
+    }
+}
+
+/// Raw bytes for keys without a more specific type, like the private exponent and label hash
+/// of the `beta_nca0_*` keys.
+impl<const N: usize> FromRawKey for [u8; N] {
+    fn from_key(key: &[u8]) -> Result<Self, KeyError> {
+        key.try_into().map_err(|_| {
+            crate::utils::ParseKeyError::LengthMismatch {
+                requested_key_len: N,
+                actual_key_len: key.len(),
+            }
+            .into()
+        })
+    }
+}
+
 impl Keyset {
     pub fn empty() -> Self {
         Self {
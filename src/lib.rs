@@ -8,10 +8,13 @@ extern crate alloc;
 
 pub mod common;
 pub mod containers;
+pub mod detect;
 pub mod error;
+pub mod formats;
 pub mod keyset;
 pub mod storage;
 pub mod utils;
+pub mod vfs;
 
 pub use error::{SwonchError, SwonchResult};
 
@@ -21,8 +24,14 @@ pub mod prelude {
     pub use super::{
         containers::{
             nca::Nca,
-            partitionfs::{hfs0, pfs0::Pfs0},
+            partitionfs::{
+                hfs0::{self, Hfs0},
+                pfs0::Pfs0,
+            },
+            romfs::RomFs,
+            xci::Xci,
         },
+        formats::{cnmt::ContentMeta, nacp::Nacp, npdm::Npdm, nro::Nro, nso::Nso},
         storage::{IStorage, Storage, VecStorage},
         SwonchError, SwonchResult,
     };
@@ -0,0 +1,206 @@
+//! The bucket tree (`BKTR`) format used by patch, sparse and compressed NCA sections to map virtual
+//! offsets to entries, e.g. which storage a range of a patched RomFS comes from.
+
+use crate::{
+    storage::{IStorage, Storage},
+    SwonchResult,
+};
+use alloc::vec::Vec;
+use binrw::{io::Cursor, BinRead};
+
+const NODE_SIZE: usize = 0x4000;
+const NODE_HEADER_SIZE: usize = 0x10;
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum BucketTreeError {
+    #[error("unsupported bucket tree version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("bucket tree node {index} is corrupted")]
+    NodeCorrupted { index: u32 },
+
+    #[error("{0} entries don't fit into the bucket tree")]
+    TooManyEntries(u32),
+
+    #[error("entry points to storage {0} which does not exist")]
+    InvalidStorageIndex(u32),
+}
+
+#[binrw::binrw]
+#[brw(little, magic = b"BKTR")]
+#[derive(Debug, Clone, Copy)]
+pub struct BucketTreeHeader {
+    pub version: u32,
+    pub entry_count: u32,
+    reserved: u32,
+}
+
+/// Where a bucket tree is located, relative to the start of the section it belongs to.
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct BucketTreeInfo {
+    pub offset: u64,
+    pub size: u64,
+    pub header: BucketTreeHeader,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+struct NodeHeader {
+    index: u32,
+    count: u32,
+    end_offset: u64,
+}
+
+/// An entry of a bucket tree, covering everything from its virtual offset up to the next entry.
+pub trait BucketTreeEntry: for<'a> BinRead<Args<'a> = ()> {
+    /// Size of a single entry on disk.
+    const SIZE: usize;
+
+    fn virtual_offset(&self) -> u64;
+}
+
+/// A bucket tree loaded into memory. Only the entry sets are parsed, the offset nodes in
+/// front of them just exist to speed up lookups on disk.
+#[derive(Debug, Clone)]
+pub struct BucketTree<E> {
+    entries: Vec<E>,
+    end_offset: u64,
+}
+
+/// Part of a read that falls into a single entry, see [`BucketTree::segments`].
+#[derive(Debug, Clone, Copy)]
+pub struct Segment<'a, E> {
+    pub entry: &'a E,
+    /// Virtual offset of the segment.
+    pub offset: u64,
+    pub len: u64,
+    /// Virtual size of the whole entry.
+    pub entry_size: u64,
+}
+
+impl<E> Segment<'_, E>
+where
+    E: BucketTreeEntry,
+{
+    /// Offset of the segment relative to the start of its entry.
+    pub fn offset_in_entry(&self) -> u64 {
+        self.offset - self.entry.virtual_offset()
+    }
+}
+
+impl<E: BucketTreeEntry> BucketTree<E> {
+    /// Reads a tree from `storage`, which starts at the offset node and contains the whole table.
+    pub fn read(storage: &Storage, header: &BucketTreeHeader) -> SwonchResult<Self> {
+        if header.version > 1 {
+            return Err(BucketTreeError::UnsupportedVersion(header.version).into());
+        }
+
+        // the table has to be able to hold all entries before anything gets allocated for them
+        let entry_count = header.entry_count as usize;
+        if entry_count as u64 > storage.length()? / E::SIZE as u64 {
+            return Err(BucketTreeError::TooManyEntries(header.entry_count).into());
+        }
+
+        let mut entries = Vec::with_capacity(entry_count);
+        let mut node = vec![0; NODE_SIZE];
+
+        storage.read_at(0, &mut node[..NODE_HEADER_SIZE])?;
+        let end_offset = NodeHeader::read(&mut Cursor::new(&node[..NODE_HEADER_SIZE]))?.end_offset;
+
+        let entry_sets_offset = (1 + Self::l2_node_count(entry_count)) * NODE_SIZE;
+        for index in 0..Self::entry_set_count(entry_count) {
+            storage.read_at((entry_sets_offset + index * NODE_SIZE) as u64, &mut node)?;
+
+            let mut cursor = Cursor::new(&node);
+            let set = NodeHeader::read(&mut cursor)?;
+            if set.index as usize != index || set.count as usize > Self::entries_per_set() {
+                return Err(BucketTreeError::NodeCorrupted { index: set.index }.into());
+            }
+
+            for _ in 0..set.count {
+                let entry = E::read_le(&mut cursor)?;
+
+                // entries have to be sorted and inside of the tree, otherwise they'd have no size
+                let sorted = entries
+                    .last()
+                    .is_none_or(|prev: &E| prev.virtual_offset() < entry.virtual_offset());
+                if !sorted || entry.virtual_offset() >= end_offset {
+                    return Err(BucketTreeError::NodeCorrupted { index: set.index }.into());
+                }
+
+                entries.push(entry);
+            }
+        }
+
+        Ok(Self {
+            entries,
+            end_offset,
+        })
+    }
+
+    fn entries_per_set() -> usize {
+        (NODE_SIZE - NODE_HEADER_SIZE) / E::SIZE
+    }
+
+    fn entry_set_count(entry_count: usize) -> usize {
+        entry_count.div_ceil(Self::entries_per_set())
+    }
+
+    /// Number of nodes between the first offset node and the entry sets, only needed for huge trees.
+    fn l2_node_count(entry_count: usize) -> usize {
+        let offsets_per_node = (NODE_SIZE - NODE_HEADER_SIZE) / 8;
+        let entry_set_count = Self::entry_set_count(entry_count);
+
+        if entry_set_count <= offsets_per_node {
+            return 0;
+        }
+
+        let l2_count = entry_set_count.div_ceil(offsets_per_node);
+        (entry_set_count - offsets_per_node.saturating_sub(l2_count - 1)).div_ceil(offsets_per_node)
+    }
+
+    pub fn entries(&self) -> &[E] {
+        &self.entries
+    }
+
+    /// Virtual offset at which the last entry ends.
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    /// Splits the range `offset..offset + len` at entry boundaries, stopping at the end of the tree.
+    pub fn segments(&self, offset: u64, len: u64) -> impl Iterator<Item = Segment<'_, E>> {
+        let end = core::cmp::min(offset.saturating_add(len), self.end_offset);
+        let mut index = self
+            .entries
+            .partition_point(|e| e.virtual_offset() <= offset)
+            .checked_sub(1);
+        let mut pos = offset;
+
+        core::iter::from_fn(move || {
+            let entry = self.entries.get(index?)?;
+            if pos >= end {
+                return None;
+            }
+
+            let next = index? + 1;
+            let entry_end = self
+                .entries
+                .get(next)
+                .map_or(self.end_offset, |e| e.virtual_offset());
+            let segment = Segment {
+                entry,
+                offset: pos,
+                len: core::cmp::min(entry_end, end) - pos,
+                entry_size: entry_end - entry.virtual_offset(),
+            };
+
+            pos += segment.len;
+            index = Some(next);
+            Some(segment)
+        })
+    }
+}
@@ -0,0 +1,285 @@
+//! Storages decompressing their data on the fly.
+
+use super::{
+    bucket_tree::{BucketTree, BucketTreeEntry},
+    IStorage, Storage,
+};
+use crate::{sync_impl::Mutex, SwonchResult};
+use alloc::vec::Vec;
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+
+/// How many decompressed entries are kept around.
+const CACHE_SIZE: usize = 4;
+
+/// LZ4 can't expand data by more than this, anything larger is a corrupted entry.
+pub(crate) const MAX_LZ4_RATIO: u64 = 0x100;
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum CompressionError {
+    #[error("failed to decompress the entry at {offset:#x}")]
+    DecompressionFailed { offset: u64 },
+
+    #[error("the compressed entry at {offset:#x} is out of bounds")]
+    EntryOutOfBounds { offset: u64 },
+}
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CompressionType {
+    None = 0,
+    Zeros = 1,
+    Lz4 = 3,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionEntry {
+    pub virtual_offset: u64,
+    pub physical_offset: u64,
+    pub compression_type: CompressionType,
+    pub compression_level: i8,
+    reserved: [u8; 2],
+    pub physical_size: u32,
+}
+
+impl BucketTreeEntry for CompressionEntry {
+    const SIZE: usize = 0x18;
+
+    fn virtual_offset(&self) -> u64 {
+        self.virtual_offset
+    }
+}
+
+/// Decompresses the entries of the compression table on demand. The last few decompressed
+/// entries are cached, so reading through a file sequentially only decompresses every entry once.
+#[derive(Debug)]
+pub struct CompressedStorage {
+    data: Storage,
+    table: BucketTree<CompressionEntry>,
+    cache: Mutex<AllocRingBuffer<(u64, Vec<u8>)>>,
+}
+
+impl CompressedStorage {
+    pub fn new(data: Storage, table: BucketTree<CompressionEntry>) -> Self {
+        Self {
+            data,
+            table,
+            cache: Mutex::new(AllocRingBuffer::new(CACHE_SIZE)),
+        }
+    }
+
+    pub fn table(&self) -> &BucketTree<CompressionEntry> {
+        &self.table
+    }
+
+    /// Decompresses an LZ4 entry of `size` bytes, or takes it from the cache, and copies the
+    /// part starting at `offset_in_entry` into `buf`.
+    fn read_lz4(
+        &self,
+        entry: &CompressionEntry,
+        size: u64,
+        offset_in_entry: u64,
+        buf: &mut [u8],
+    ) -> SwonchResult<()> {
+        let mut cache = self.cache.lock();
+
+        if !cache
+            .iter()
+            .any(|(offset, _)| *offset == entry.virtual_offset)
+        {
+            // both sizes come from the table, check them before allocating anything
+            let physical_size = entry.physical_size as u64;
+            let data_len = self.data.length()?;
+            let physical_end = entry.physical_offset.checked_add(physical_size);
+            if physical_end.is_none_or(|end| end > data_len) || size > physical_size * MAX_LZ4_RATIO
+            {
+                return Err(CompressionError::EntryOutOfBounds {
+                    offset: entry.virtual_offset,
+                }
+                .into());
+            }
+
+            let mut compressed = vec![0; physical_size as usize];
+            self.data.read_at(entry.physical_offset, &mut compressed)?;
+
+            let mut decompressed = vec![0; size as usize];
+            // a short block would leave the rest of the entry zeroed
+            let len = lz4_flex::block::decompress_into(&compressed, &mut decompressed).ok();
+            if len != Some(decompressed.len()) {
+                return Err(CompressionError::DecompressionFailed {
+                    offset: entry.virtual_offset,
+                }
+                .into());
+            }
+
+            cache.push((entry.virtual_offset, decompressed));
+        }
+
+        let (_, decompressed) = cache
+            .iter()
+            .find(|(offset, _)| *offset == entry.virtual_offset)
+            .expect("BUG: entry was just inserted into the cache");
+        buf.copy_from_slice(&decompressed[offset_in_entry as usize..][..buf.len()]);
+
+        Ok(())
+    }
+}
+
+impl IStorage for CompressedStorage {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        let mut cnt = 0;
+
+        for segment in self.table.segments(offset, buf.len() as u64) {
+            let entry = segment.entry;
+            let buf = &mut buf[(segment.offset - offset) as usize..][..segment.len as usize];
+
+            let len = match entry.compression_type {
+                CompressionType::None => self
+                    .data
+                    .read_at(entry.physical_offset + segment.offset_in_entry(), buf)?,
+                CompressionType::Zeros => {
+                    buf.fill(0);
+                    segment.len
+                }
+                CompressionType::Lz4 => {
+                    self.read_lz4(entry, segment.entry_size, segment.offset_in_entry(), buf)?;
+                    segment.len
+                }
+            };
+
+            cnt += len;
+            if len < segment.len {
+                break;
+            }
+        }
+
+        Ok(cnt)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        Ok(self.table.end_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::{bucket_tree::BucketTreeHeader, VecStorage},
+        SwonchError,
+    };
+    use binrw::{io::Cursor, BinRead};
+
+    // virtual offset, physical offset, type, physical size
+    type Entry = (u64, u64, CompressionType, u32);
+
+    fn build_table(
+        entries: &[Entry],
+        end_offset: u64,
+    ) -> SwonchResult<BucketTree<CompressionEntry>> {
+        let mut table = vec![0; 0x8000];
+        table[8..0x10].copy_from_slice(&end_offset.to_le_bytes());
+        table[0x4004..0x4008].copy_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (idx, (virt, phys, ty, size)) in entries.iter().enumerate() {
+            let entry = &mut table[0x4010 + idx * CompressionEntry::SIZE..];
+            entry[..8].copy_from_slice(&virt.to_le_bytes());
+            entry[8..0x10].copy_from_slice(&phys.to_le_bytes());
+            entry[0x10] = *ty as u8;
+            entry[0x14..0x18].copy_from_slice(&size.to_le_bytes());
+        }
+
+        let mut header = *b"BKTR\x01\0\0\0\0\0\0\0\0\0\0\0";
+        header[8..0xc].copy_from_slice(&(entries.len() as u32).to_le_bytes());
+        let header = BucketTreeHeader::read(&mut Cursor::new(header))?;
+        BucketTree::read(&VecStorage::new(table), &header)
+    }
+
+    #[test]
+    fn decompresses_entries() -> SwonchResult<()> {
+        let plain: Vec<u8> = (0..0x100u32).map(|i| (i / 0x10) as u8).collect();
+        let compressed = lz4_flex::block::compress(&plain);
+        let data = VecStorage::new([&compressed[..], b"raw!"].concat());
+
+        let table = build_table(
+            &[
+                (0u64, 0u64, CompressionType::Lz4, compressed.len() as u32),
+                (0x100, compressed.len() as u64, CompressionType::None, 4),
+                (0x104, 0, CompressionType::Zeros, 0),
+            ],
+            0x108,
+        )?;
+        let storage = CompressedStorage::new(data, table);
+
+        let mut buf = [0xff; 0x10];
+        assert_eq!(storage.read_at(0xf8, &mut buf)?, 0x10);
+        assert_eq!(buf, *b"\x0f\x0f\x0f\x0f\x0f\x0f\x0f\x0fraw!\0\0\0\0");
+
+        // served from the cache
+        let mut buf = [0; 0x20];
+        storage.read_at(0x20, &mut buf)?;
+        assert_eq!(buf, plain[0x20..0x40]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_corrupted_lz4_entries() -> SwonchResult<()> {
+        let plain = [0x42; 0x100];
+        let compressed = lz4_flex::block::compress(&plain);
+        let read = |entries: &[Entry], end_offset| -> SwonchResult<u64> {
+            let data = VecStorage::new(compressed.clone());
+            let storage = CompressedStorage::new(data, build_table(entries, end_offset)?);
+            storage.read_at(0, &mut [0; 0x10])
+        };
+        let size = compressed.len() as u32;
+
+        // the entry decompresses to less than its size in the table
+        assert!(matches!(
+            read(&[(0, 0, CompressionType::Lz4, size)], 0x200),
+            Err(SwonchError::Compression(
+                CompressionError::DecompressionFailed { offset: 0 }
+            ))
+        ));
+
+        // past the end of the data, and more than LZ4 can expand to
+        for (physical_offset, end_offset) in [(1, 0x100), (0, size as u64 * MAX_LZ4_RATIO + 1)] {
+            assert!(matches!(
+                read(
+                    &[(0, physical_offset, CompressionType::Lz4, size)],
+                    end_offset
+                ),
+                Err(SwonchError::Compression(
+                    CompressionError::EntryOutOfBounds { offset: 0 }
+                ))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_on_short_read() -> SwonchResult<()> {
+        // the uncompressed entry claims 8 bytes, but the data ends after 4
+        let table = build_table(
+            &[
+                (0, 0, CompressionType::None, 8),
+                (8, 0, CompressionType::Zeros, 0),
+            ],
+            0x10,
+        )?;
+        let storage = CompressedStorage::new(VecStorage::new(b"raw!".to_vec()), table);
+
+        let mut buf = [0xff; 0x10];
+        assert_eq!(storage.read_at(0, &mut buf)?, 4);
+        assert_eq!(&buf[..4], b"raw!");
+
+        Ok(())
+    }
+}
@@ -10,15 +10,27 @@ use core::fmt;
 
 const BUF_SIZE: usize = 1024 * 1024;
 
+/// Initial counter for NCA sections: the secure value and generation make up the upper half,
+/// the lower half is the block index of `offset`.
+pub fn nca_ctr_iv(secure_value: u32, generation: u32, offset: u64) -> [u8; 0x10] {
+    let mut iv = [0; 0x10];
+    iv[..4].copy_from_slice(&secure_value.to_be_bytes());
+    iv[4..8].copy_from_slice(&generation.to_be_bytes());
+    iv[8..].copy_from_slice(&(offset >> 4).to_be_bytes());
+    iv
+}
+
+/// AES-CTR over `parent`, generic over the counter flavour. Defaults to a little endian
+/// counter, NCA sections use [`ctr::Ctr64BE`].
 #[derive(Clone)]
-pub struct AesCtrStorageImpl {
+pub struct AesCtrStorageImpl<C = Ctr64LE<Aes128>> {
     parent: Storage,
-    aes_ctx: Arc<Mutex<Ctr64LE<Aes128>>>,
+    aes_ctx: Arc<Mutex<C>>,
     write_buf: Arc<Mutex<Vec<u8>>>,
 }
 
-impl AesCtrStorageImpl {
-    pub fn new(parent: Storage, aes_ctx: Ctr64LE<Aes128>) -> Self {
+impl<C: StreamCipher + StreamCipherSeek + 'static> AesCtrStorageImpl<C> {
+    pub fn new(parent: Storage, aes_ctx: C) -> Self {
         Self {
             parent,
             aes_ctx: Arc::new(Mutex::new(aes_ctx)),
@@ -27,7 +39,7 @@ impl AesCtrStorageImpl {
     }
 }
 
-impl fmt::Debug for AesCtrStorageImpl {
+impl<C> fmt::Debug for AesCtrStorageImpl<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AesCtrStorageImpl")
             .field("parent", &self.parent)
@@ -35,7 +47,7 @@ impl fmt::Debug for AesCtrStorageImpl {
     }
 }
 
-impl IStorage for AesCtrStorageImpl {
+impl<C: StreamCipher + StreamCipherSeek + 'static> IStorage for AesCtrStorageImpl<C> {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
         let mut aes = self.aes_ctx.lock();
         aes.seek(offset);
@@ -0,0 +1,186 @@
+use super::ctr::nca_ctr_iv;
+use crate::{
+    prelude::*,
+    storage::bucket_tree::{BucketTree, BucketTreeEntry},
+};
+use aes::{
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
+    Aes128,
+};
+use ctr::Ctr64BE;
+
+use core::fmt;
+
+#[binrw::binrw]
+#[brw(little, repr(u8))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AesCtrExEncryption {
+    Encrypted = 0,
+    NotEncrypted = 1,
+}
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct AesCtrExEntry {
+    pub offset: u64,
+    pub encryption: AesCtrExEncryption,
+    reserved: [u8; 3],
+    /// Replaces the generation of the section in the counter for this entry.
+    pub generation: u32,
+}
+
+impl BucketTreeEntry for AesCtrExEntry {
+    const SIZE: usize = 0x10;
+
+    fn virtual_offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// AES-CTR where the generation part of the counter changes depending on the entry of the
+/// AES-CTR-EX table a read falls into, used by patch sections to mix data of several updates.
+#[derive(Clone)]
+pub struct AesCtrExStorageImpl {
+    parent: Storage,
+    key: [u8; 0x10],
+    secure_value: u32,
+    counter_offset: u64,
+    table: BucketTree<AesCtrExEntry>,
+}
+
+impl AesCtrExStorageImpl {
+    /// `counter_offset` is the offset of `parent` within the NCA.
+    pub fn new(
+        parent: Storage,
+        key: [u8; 0x10],
+        secure_value: u32,
+        counter_offset: u64,
+        table: BucketTree<AesCtrExEntry>,
+    ) -> Self {
+        Self {
+            parent,
+            key,
+            secure_value,
+            counter_offset,
+            table,
+        }
+    }
+}
+
+impl fmt::Debug for AesCtrExStorageImpl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AesCtrExStorageImpl")
+            .field("parent", &self.parent)
+            .field("counter_offset", &self.counter_offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IStorage for AesCtrExStorageImpl {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        let mut cnt = 0;
+
+        for segment in self.table.segments(offset, buf.len() as u64) {
+            let buf = &mut buf[(segment.offset - offset) as usize..][..segment.len as usize];
+            let len = self.parent.read_at(segment.offset, buf)?;
+
+            if segment.entry.encryption == AesCtrExEncryption::Encrypted {
+                let iv = nca_ctr_iv(
+                    self.secure_value,
+                    segment.entry.generation,
+                    self.counter_offset,
+                );
+                let mut aes = Ctr64BE::<Aes128>::new(&self.key.into(), &iv.into());
+                aes.seek(segment.offset);
+                aes.apply_keystream(&mut buf[..len as usize]);
+            }
+
+            cnt += len;
+            if len < segment.len {
+                break;
+            }
+        }
+
+        Ok(cnt)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        Ok(self.table.end_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{bucket_tree::BucketTreeHeader, VecStorage};
+    use binrw::{io::Cursor, BinRead};
+
+    const KEY: [u8; 0x10] = *b"aes-ctr-ex-key!!";
+    const SECURE_VALUE: u32 = 0x11223344;
+    const COUNTER_OFFSET: u64 = 0xc00;
+
+    // (offset, encrypted, generation)
+    const ENTRIES: [(u64, bool, u32); 3] = [(0, true, 1), (0x10, false, 0), (0x20, true, 2)];
+    const END_OFFSET: u64 = 0x40;
+
+    // a table with a single entry set, preceded by an offset node
+    fn build_table() -> Vec<u8> {
+        let mut table = vec![0; 0x8000];
+        table[8..0x10].copy_from_slice(&END_OFFSET.to_le_bytes());
+
+        let set = &mut table[0x4000..];
+        set[4..8].copy_from_slice(&(ENTRIES.len() as u32).to_le_bytes());
+        set[8..0x10].copy_from_slice(&END_OFFSET.to_le_bytes());
+        for (idx, (offset, encrypted, generation)) in ENTRIES.iter().enumerate() {
+            let entry = &mut set[0x10 + idx * AesCtrExEntry::SIZE..];
+            entry[..8].copy_from_slice(&offset.to_le_bytes());
+            entry[8] = !encrypted as u8;
+            entry[0xc..0x10].copy_from_slice(&generation.to_le_bytes());
+        }
+
+        table
+    }
+
+    #[test]
+    fn decrypts_per_entry_generation() -> SwonchResult<()> {
+        let plaintext: Vec<u8> = (0..END_OFFSET as u8).collect();
+
+        let mut data = plaintext.clone();
+        for (idx, (offset, encrypted, generation)) in ENTRIES.iter().enumerate() {
+            let end = ENTRIES.get(idx + 1).map_or(END_OFFSET, |e| e.0);
+            if *encrypted {
+                let iv = nca_ctr_iv(SECURE_VALUE, *generation, COUNTER_OFFSET);
+                let mut aes = Ctr64BE::<Aes128>::new(&KEY.into(), &iv.into());
+                aes.seek(*offset);
+                aes.apply_keystream(&mut data[*offset as usize..end as usize]);
+            }
+        }
+
+        let header = BucketTreeHeader::read(&mut Cursor::new(b"BKTR\x01\0\0\0\x03\0\0\0\0\0\0\0"))?;
+        let table = BucketTree::read(&VecStorage::new(build_table()), &header)?;
+        let storage = AesCtrExStorageImpl::new(
+            VecStorage::new(data),
+            KEY,
+            SECURE_VALUE,
+            COUNTER_OFFSET,
+            table,
+        );
+
+        let mut buf = vec![0; END_OFFSET as usize];
+        assert_eq!(storage.read_at(0, &mut buf)?, END_OFFSET);
+        assert_eq!(buf, plaintext);
+
+        // reads crossing entries, not starting on a block boundary
+        let mut buf = [0; 0x30];
+        assert_eq!(storage.read_at(0x8, &mut buf)?, 0x30);
+        assert_eq!(buf, plaintext[0x8..0x38]);
+
+        Ok(())
+    }
+}
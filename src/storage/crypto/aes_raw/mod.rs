@@ -1,2 +1,3 @@
 pub mod ctr;
+pub mod ctr_ex;
 pub mod xts;
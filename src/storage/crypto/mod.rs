@@ -9,4 +9,6 @@ pub type AesXtsStorage = BlockBufferStorage<aes_raw::xts::AesXtsStorage, 0x200>;
 /// A buffered and self aligning wrapper storage for AES128 in XTS mode with Nintendo's custom tweak.
 pub type AesXtsnStorage = BlockBufferStorage<aes_raw::xts::AesXtsnStorage, 0x200>;
 
-pub type AesCtrStorage = aes_raw::ctr::AesCtrStorageImpl;
+pub type AesCtrStorage<C = ctr::Ctr64LE<aes::Aes128>> = aes_raw::ctr::AesCtrStorageImpl<C>;
+
+pub type AesCtrExStorage = aes_raw::ctr_ex::AesCtrExStorageImpl;
@@ -0,0 +1,161 @@
+//! A storage stitched together from ranges of other storages, as described by a [`BucketTree`].
+
+use super::{
+    bucket_tree::{BucketTree, BucketTreeEntry, BucketTreeError},
+    IStorage, Storage,
+};
+use crate::SwonchResult;
+
+#[binrw::binrw]
+#[brw(little)]
+#[derive(Debug, Clone, Copy)]
+pub struct IndirectEntry {
+    pub virtual_offset: u64,
+    pub physical_offset: u64,
+    /// Index of the storage the data of this entry comes from.
+    pub storage_index: u32,
+}
+
+impl BucketTreeEntry for IndirectEntry {
+    const SIZE: usize = 0x14;
+
+    fn virtual_offset(&self) -> u64 {
+        self.virtual_offset
+    }
+}
+
+/// Maps every entry of the relocation table to a range in one of two storages. For patches
+/// the first one is the section of the base NCA and the second one the patch data itself.
+#[derive(Debug)]
+pub struct IndirectStorage {
+    storages: [Storage; 2],
+    table: BucketTree<IndirectEntry>,
+}
+
+impl IndirectStorage {
+    pub fn new(storages: [Storage; 2], table: BucketTree<IndirectEntry>) -> Self {
+        Self { storages, table }
+    }
+
+    pub fn table(&self) -> &BucketTree<IndirectEntry> {
+        &self.table
+    }
+}
+
+impl IStorage for IndirectStorage {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        let mut cnt = 0;
+
+        for segment in self.table.segments(offset, buf.len() as u64) {
+            let storage = self
+                .storages
+                .get(segment.entry.storage_index as usize)
+                .ok_or(BucketTreeError::InvalidStorageIndex(
+                    segment.entry.storage_index,
+                ))?;
+
+            let buf = &mut buf[(segment.offset - offset) as usize..][..segment.len as usize];
+            let len = storage.read_at(
+                segment.entry.physical_offset + segment.offset_in_entry(),
+                buf,
+            )?;
+
+            cnt += len;
+            if len < segment.len {
+                break;
+            }
+        }
+
+        Ok(cnt)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        Ok(self.table.end_offset())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        storage::{bucket_tree::BucketTreeHeader, VecStorage},
+        SwonchError,
+    };
+    use binrw::{io::Cursor, BinRead};
+
+    // a table with a single entry set, preceded by an offset node
+    pub(crate) fn build_table(entries: &[(u64, u64, u32)], end_offset: u64) -> Vec<u8> {
+        let mut table = vec![0; 0x8000];
+        table[8..0x10].copy_from_slice(&end_offset.to_le_bytes());
+
+        let set = &mut table[0x4000..];
+        set[4..8].copy_from_slice(&(entries.len() as u32).to_le_bytes());
+        set[8..0x10].copy_from_slice(&end_offset.to_le_bytes());
+        for (idx, (virt, phys, storage)) in entries.iter().enumerate() {
+            let entry = &mut set[0x10 + idx * IndirectEntry::SIZE..];
+            entry[..8].copy_from_slice(&virt.to_le_bytes());
+            entry[8..0x10].copy_from_slice(&phys.to_le_bytes());
+            entry[0x10..0x14].copy_from_slice(&storage.to_le_bytes());
+        }
+
+        table
+    }
+
+    #[test]
+    fn reads_across_entries() -> SwonchResult<()> {
+        let header = BucketTreeHeader::read(&mut Cursor::new(b"BKTR\x01\0\0\0\x03\0\0\0\0\0\0\0"))?;
+        let table = VecStorage::new(build_table(&[(0, 4, 0), (4, 0, 1), (6, 0, 0)], 10));
+        let table = BucketTree::read(&table, &header)?;
+
+        let base = VecStorage::new(b"01234567".to_vec());
+        let patch = VecStorage::new(b"ab".to_vec());
+        let storage = IndirectStorage::new([base, patch], table);
+
+        let mut buf = [0; 12];
+        assert_eq!(storage.read_at(0, &mut buf)?, 10);
+        assert_eq!(&buf[..10], b"4567ab0123");
+
+        let mut buf = [0; 3];
+        assert_eq!(storage.read_at(5, &mut buf)?, 3);
+        assert_eq!(&buf, b"b01");
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_corrupted_entries() -> SwonchResult<()> {
+        let header = BucketTreeHeader::read(&mut Cursor::new(b"BKTR\x01\0\0\0\x03\0\0\0\0\0\0\0"))?;
+
+        // out of order, duplicated and past the end of the tree
+        for entries in [
+            [(0, 4, 0), (6, 0, 1), (4, 0, 0)],
+            [(0, 4, 0), (4, 0, 1), (4, 0, 0)],
+            [(0, 4, 0), (4, 0, 1), (10, 0, 0)],
+        ] {
+            let table = VecStorage::new(build_table(&entries, 10));
+            assert!(matches!(
+                BucketTree::<IndirectEntry>::read(&table, &header),
+                Err(SwonchError::BucketTree(BucketTreeError::NodeCorrupted {
+                    index: 0
+                }))
+            ));
+        }
+
+        // a huge entry count isn't allocated up front
+        let mut header = header;
+        header.entry_count = u32::MAX;
+        let table = VecStorage::new(build_table(&[(0, 4, 0)], 10));
+        assert!(matches!(
+            BucketTree::<IndirectEntry>::read(&table, &header),
+            Err(SwonchError::BucketTree(BucketTreeError::TooManyEntries(
+                u32::MAX
+            )))
+        ));
+
+        Ok(())
+    }
+}
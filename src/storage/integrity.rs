@@ -0,0 +1,294 @@
+//! Storages validating data against hash tables while reading it.
+
+use super::{IStorage, Storage};
+use crate::{sync_impl::RwLock, utils::HexArray, Integrity, SwonchResult};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use sha2::{
+    digest::{consts::U32, Digest},
+    Sha256,
+};
+
+/// A digest usable for the hash tables, SHA-256 or SHA3-256 depending on the hash type.
+pub trait HashDigest: Digest<OutputSize = U32> + core::fmt::Debug + 'static {}
+
+impl<D: Digest<OutputSize = U32> + core::fmt::Debug + 'static> HashDigest for D {}
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum IntegrityError {
+    #[error("hash mismatch on the master hash")]
+    MasterHashMismatch {
+        hash_in_header: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+    },
+
+    #[error("hash mismatch on the block at {offset:#x}")]
+    BlockHashMismatch {
+        offset: u64,
+        hash_in_table: [u8; 0x20],
+        actual_hash: [u8; 0x20],
+    },
+}
+
+/// A table of hashes, one for every block of the storage it covers.
+#[derive(Debug)]
+struct HashTable<D> {
+    hashes: Storage,
+    block_size: u64,
+    integrity: Integrity,
+    digest: PhantomData<D>,
+}
+
+impl<D: HashDigest> HashTable<D> {
+    fn new(hashes: Storage, block_size: u64, integrity: Integrity) -> Self {
+        Self {
+            hashes,
+            block_size,
+            integrity,
+            digest: PhantomData,
+        }
+    }
+
+    fn verify_block(&self, index: u64, block: &[u8]) -> SwonchResult<()> {
+        let mut hash_in_table = [0; 0x20];
+        self.hashes.read_at(index * 0x20, &mut hash_in_table)?;
+
+        if let Err(hash) = crate::utils::validate_hash::<D>(block, &hash_in_table) {
+            let offset = index * self.block_size;
+            match self.integrity {
+                Integrity::WarnOnly => log::error!(
+                    "hash mismatch on the block at {offset:#x}. hash table claims {:?} but actual hash is {}",
+                    HexArray(hash_in_table),
+                    HexArray::<0x20>(hash.into()),
+                ),
+                Integrity::ErrorOnMismatch => {
+                    return Err(IntegrityError::BlockHashMismatch {
+                        offset,
+                        hash_in_table,
+                        actual_hash: hash.into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads every block overlapping the read from `data`, hands it to `verify` together with
+    /// its index and the amount of bytes read, then copies the requested part into `buf`.
+    fn read_blocks(
+        &self,
+        data: &Storage,
+        offset: u64,
+        buf: &mut [u8],
+        mut verify: impl FnMut(u64, &mut [u8], usize) -> SwonchResult<()>,
+    ) -> SwonchResult<u64> {
+        let len = core::cmp::min(buf.len() as u64, data.length()?.saturating_sub(offset));
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let mut block = vec![0; self.block_size as usize];
+        for index in offset / self.block_size..(offset + len).div_ceil(self.block_size) {
+            let block_start = index * self.block_size;
+            let read = data.read_at(block_start, &mut block)? as usize;
+            verify(index, &mut block, read)?;
+
+            // copy the part of the block that overlaps with the read
+            let start = core::cmp::max(block_start, offset);
+            let end = core::cmp::min(block_start + read as u64, offset + len);
+            buf[(start - offset) as usize..(end - offset) as usize].copy_from_slice(
+                &block[(start - block_start) as usize..(end - block_start) as usize],
+            );
+        }
+
+        Ok(len)
+    }
+}
+
+/// Verifies every block of `data` against the hash at the same index in `hash_table` before
+/// handing it out. The last block is hashed as is if it is shorter than the block size.
+///
+/// Despite the name the digest can be swapped out, newer sections use SHA3-256 instead.
+#[derive(Debug)]
+pub struct HierarchicalSha256Storage<D = Sha256> {
+    hash_table: HashTable<D>,
+    data: Storage,
+}
+
+impl<D: HashDigest> HierarchicalSha256Storage<D> {
+    pub fn new(hash_table: Storage, data: Storage, block_size: u64, integrity: Integrity) -> Self {
+        Self {
+            hash_table: HashTable::new(hash_table, block_size, integrity),
+            data,
+        }
+    }
+}
+
+impl<D: HashDigest> IStorage for HierarchicalSha256Storage<D> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        self.hash_table
+            .read_blocks(&self.data, offset, buf, |index, block, read| {
+                self.hash_table.verify_block(index, &block[..read])
+            })
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        self.data.length()
+    }
+}
+
+/// A single level of an IVFC hash tree. Unlike [`HierarchicalSha256Storage`] the last block is
+/// zero padded to the block size before hashing, and blocks that passed verification once are
+/// remembered so they don't get hashed again.
+#[derive(Debug)]
+pub struct IntegrityVerificationStorage<D = Sha256> {
+    hash_table: HashTable<D>,
+    data: Storage,
+    verified: RwLock<Vec<bool>>,
+}
+
+impl<D: HashDigest> IntegrityVerificationStorage<D> {
+    pub fn new(
+        hash_table: Storage,
+        data: Storage,
+        block_size: u64,
+        integrity: Integrity,
+    ) -> SwonchResult<Self> {
+        let block_cnt = data.length()?.div_ceil(block_size);
+
+        Ok(Self {
+            hash_table: HashTable::new(hash_table, block_size, integrity),
+            data,
+            verified: RwLock::new(vec![false; block_cnt as usize]),
+        })
+    }
+}
+
+impl<D: HashDigest> IStorage for IntegrityVerificationStorage<D> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        self.hash_table
+            .read_blocks(&self.data, offset, buf, |index, block, read| {
+                if self.verified.read()[index as usize] {
+                    return Ok(());
+                }
+
+                block[read..].fill(0);
+                self.hash_table.verify_block(index, block)?;
+                self.verified.write()[index as usize] = true;
+                Ok(())
+            })
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        self.data.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::VecStorage;
+    use sha3::Sha3_256;
+
+    fn storage_over(data: Vec<u8>, integrity: Integrity) -> HierarchicalSha256Storage {
+        let hash_table: Vec<u8> = data.chunks(0x10).flat_map(Sha256::digest).collect();
+        HierarchicalSha256Storage::new(
+            VecStorage::new(hash_table),
+            VecStorage::new(data),
+            0x10,
+            integrity,
+        )
+    }
+
+    #[test]
+    fn detects_corrupted_blocks() -> SwonchResult<()> {
+        let data: Vec<u8> = (0..0x38).collect();
+        let storage = storage_over(data.clone(), Integrity::ErrorOnMismatch);
+
+        // unaligned read spanning three blocks, including the short last one
+        let mut buf = [0; 0x30];
+        assert_eq!(storage.read_at(0x8, &mut buf)?, 0x30);
+        assert_eq!(buf, data[0x8..]);
+
+        let mut corrupted = storage_over(data.clone(), Integrity::ErrorOnMismatch);
+        corrupted.data = VecStorage::new([&data[..0x1f], &[0xff], &data[0x20..]].concat());
+        assert!(corrupted.read_at(0, &mut [0; 0x10]).is_ok());
+        assert!(matches!(
+            corrupted.read_at(0x18, &mut [0; 0x10]),
+            Err(crate::SwonchError::Integrity(
+                IntegrityError::BlockHashMismatch { offset: 0x10, .. }
+            ))
+        ));
+
+        corrupted.hash_table.integrity = Integrity::WarnOnly;
+        assert!(corrupted.read_at(0x18, &mut [0; 0x10]).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn remembers_verified_blocks() -> SwonchResult<()> {
+        let data: Vec<u8> = (0..0x18).collect();
+        let mut padded = data.clone();
+        padded.resize(0x20, 0);
+        let hash_table: Vec<u8> = padded.chunks(0x10).flat_map(Sha256::digest).collect();
+
+        let data = VecStorage::new_mut(data);
+        let storage = IntegrityVerificationStorage::<Sha256>::new(
+            VecStorage::new(hash_table),
+            data.clone(),
+            0x10,
+            Integrity::ErrorOnMismatch,
+        )?;
+
+        // the short last block only matches when zero padded
+        let mut buf = [0; 0x18];
+        assert_eq!(storage.read_at(0, &mut buf)?, 0x18);
+
+        // already verified blocks aren't hashed again
+        data.write_at(0, &[0xff])?;
+        assert!(storage.read_at(0, &mut buf).is_ok());
+        assert_eq!(buf[0], 0xff);
+
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_sha3_256_blocks() -> SwonchResult<()> {
+        let data: Vec<u8> = (0..0x20).collect();
+        let open = |hash_table: Vec<u8>| {
+            IntegrityVerificationStorage::<Sha3_256>::new(
+                VecStorage::new(hash_table),
+                VecStorage::new(data.clone()),
+                0x10,
+                Integrity::ErrorOnMismatch,
+            )
+        };
+
+        let storage = open(data.chunks(0x10).flat_map(Sha3_256::digest).collect())?;
+        let mut buf = [0; 0x20];
+        assert_eq!(storage.read_at(0, &mut buf)?, 0x20);
+        assert_eq!(buf[..], data[..]);
+
+        // a SHA-256 hash table doesn't match when the section is hashed with SHA3-256
+        let storage = open(data.chunks(0x10).flat_map(Sha256::digest).collect())?;
+        assert!(matches!(
+            storage.read_at(0, &mut buf),
+            Err(crate::SwonchError::Integrity(
+                IntegrityError::BlockHashMismatch { offset: 0, .. }
+            ))
+        ));
+
+        Ok(())
+    }
+}
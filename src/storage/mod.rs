@@ -10,14 +10,22 @@ use crate::SwonchResult;
 #[cfg(not(feature = "arc_storage"))]
 use alloc::rc::Rc;
 
+pub mod bucket_tree;
+pub mod compressed;
 pub mod crypto;
+pub mod indirect;
+pub mod integrity;
 pub mod mapper;
 mod memory;
+pub mod region_switch;
+pub mod sparse;
 pub mod stdio;
 pub mod substorage;
 
 pub use self::{
-    mapper::FromStorage, memory::VecStorage, stdio::StorageStdioWrapper, substorage::SubStorage,
+    compressed::CompressedStorage, indirect::IndirectStorage, mapper::FromStorage,
+    memory::VecStorage, region_switch::RegionSwitchStorage, sparse::SparseStorage,
+    stdio::StorageStdioWrapper, substorage::SubStorage,
 };
 
 pub trait IStorage: Any + core::fmt::Debug + 'static {
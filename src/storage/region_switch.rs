@@ -0,0 +1,82 @@
+//! A storage reading one range from a different storage than the rest.
+
+use super::{IStorage, Storage};
+use crate::SwonchResult;
+
+/// Reads `inside` for offsets within `offset..offset + size` and `outside` for everything else.
+/// The SkipLayerHash encryption types use this to keep the hash layers in plaintext.
+#[derive(Debug)]
+pub struct RegionSwitchStorage {
+    inside: Storage,
+    outside: Storage,
+    offset: u64,
+    size: u64,
+}
+
+impl RegionSwitchStorage {
+    pub fn new(inside: Storage, outside: Storage, offset: u64, size: u64) -> Self {
+        Self {
+            inside,
+            outside,
+            offset,
+            size,
+        }
+    }
+}
+
+impl IStorage for RegionSwitchStorage {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        let region_end = self.offset.saturating_add(self.size);
+        let mut cnt = 0;
+
+        while (cnt as usize) < buf.len() {
+            let pos = offset + cnt;
+            let (storage, boundary) = match pos {
+                _ if pos < self.offset => (&self.outside, self.offset),
+                _ if pos < region_end => (&self.inside, region_end),
+                _ => (&self.outside, u64::MAX),
+            };
+
+            let len = core::cmp::min(buf.len() as u64 - cnt, boundary - pos);
+            let read = storage.read_at(pos, &mut buf[cnt as usize..][..len as usize])?;
+
+            cnt += read;
+            if read < len {
+                break;
+            }
+        }
+
+        Ok(cnt)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        self.outside.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::VecStorage;
+
+    #[test]
+    fn switches_at_region_bounds() -> SwonchResult<()> {
+        let inside = VecStorage::new(b"ABCDEFGH".to_vec());
+        let outside = VecStorage::new(b"abcdefgh".to_vec());
+        let storage = RegionSwitchStorage::new(inside, outside, 2, 3);
+
+        let mut buf = [0; 10];
+        assert_eq!(storage.read_at(0, &mut buf)?, 8);
+        assert_eq!(&buf[..8], b"abCDEfgh");
+
+        let mut buf = [0; 2];
+        assert_eq!(storage.read_at(4, &mut buf)?, 2);
+        assert_eq!(&buf, b"Ef");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+//! Storages with holes, where only the populated ranges are backed by actual data.
+
+use super::{
+    bucket_tree::BucketTree,
+    indirect::{IndirectEntry, IndirectStorage},
+    IStorage, Storage,
+};
+use crate::SwonchResult;
+
+/// An endless storage of zeros.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZeroStorage;
+
+impl IStorage for ZeroStorage {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        buf.fill(0);
+        Ok(buf.len() as u64)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        Ok(u64::MAX)
+    }
+}
+
+/// Maps the populated ranges described by the sparse table to `data`, everything else reads as zeros.
+/// Entries with storage index 0 point into `data`, the ones with index 1 are holes.
+#[derive(Debug)]
+pub struct SparseStorage(IndirectStorage);
+
+impl SparseStorage {
+    pub fn new(data: Storage, table: BucketTree<IndirectEntry>) -> Self {
+        Self(IndirectStorage::new(
+            [data, ZeroStorage.into_storage()],
+            table,
+        ))
+    }
+
+    pub fn table(&self) -> &BucketTree<IndirectEntry> {
+        self.0.table()
+    }
+}
+
+impl IStorage for SparseStorage {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> SwonchResult<u64> {
+        self.0.read_at(offset, buf)
+    }
+
+    fn is_readonly(&self) -> bool {
+        true
+    }
+
+    fn length(&self) -> SwonchResult<u64> {
+        self.0.length()
+    }
+}
@@ -126,6 +126,76 @@ pub(crate) fn decrypt_titlekey(
     Ok(dec_titlekey)
 }
 
+/// Checks an RSA-2048 PSS signature with SHA-256 over `msg`, using the public exponent 65537.
+pub(crate) fn verify_rsa2048_pss(
+    modulus: &[u8; 0x100],
+    msg: &[u8],
+    signature: &[u8; 0x100],
+) -> bool {
+    use rsa::{BigUint, Pss, RsaPublicKey};
+    use sha2::{Digest, Sha256};
+
+    let Ok(key) = RsaPublicKey::new(BigUint::from_bytes_be(modulus), BigUint::from(65537u32))
+    else {
+        return false;
+    };
+
+    key.verify(Pss::new::<Sha256>(), &Sha256::digest(msg), signature)
+        .is_ok()
+}
+
+/// Decrypts an RSA-2048 OAEP block with SHA-256 using the private `exponent`. The label is only
+/// known by its hash, so that is compared directly. `None` if the padding is invalid.
+pub(crate) fn decrypt_rsa2048_oaep(
+    modulus: &[u8; 0x100],
+    exponent: &[u8; 0x100],
+    label_hash: &[u8; 0x20],
+    ciphertext: &[u8; 0x100],
+) -> Option<alloc::vec::Vec<u8>> {
+    use rsa::BigUint;
+
+    let n = BigUint::from_bytes_be(modulus);
+    let c = BigUint::from_bytes_be(ciphertext);
+    if c >= n {
+        return None;
+    }
+
+    let m = c
+        .modpow(&BigUint::from_bytes_be(exponent), &n)
+        .to_bytes_be();
+    let mut em = [0; 0x100];
+    em[0x100usize.checked_sub(m.len())?..].copy_from_slice(&m);
+
+    let (first, masked) = em.split_at_mut(1);
+    let (seed, db) = masked.split_at_mut(0x20);
+    mgf1_xor(seed, db);
+    mgf1_xor(db, seed);
+    if first[0] != 0 || db[..0x20] != label_hash[..] {
+        return None;
+    }
+
+    // the label hash is followed by zero padding and a 0x01 separator in front of the message
+    let padded = &db[0x20..];
+    let start = padded.iter().position(|b| *b != 0)?;
+    match padded[start] {
+        1 => Some(padded[start + 1..].to_vec()),
+        _ => None,
+    }
+}
+
+/// Masks `buf` with the MGF1 SHA-256 mask generated from `seed`.
+pub(crate) fn mgf1_xor(buf: &mut [u8], seed: &[u8]) {
+    use sha2::{Digest, Sha256};
+
+    for (counter, chunk) in buf.chunks_mut(0x20).enumerate() {
+        let mask = Sha256::new()
+            .chain_update(seed)
+            .chain_update((counter as u32).to_be_bytes())
+            .finalize();
+        chunk.iter_mut().zip(mask).for_each(|(b, m)| *b ^= m);
+    }
+}
+
 pub(crate) fn validate_hash<H: sha2::Digest>(
     buf: &[u8],
     hash: &[u8],
@@ -0,0 +1,229 @@
+//! Resolves paths that descend through nested containers, e.g.
+//! `game.xci/secure/abcd.nca/section0/romfs/data/x.bin`.
+//!
+//! Whenever a path component refers to a file, the type of that file is detected with
+//! [`detect`](crate::detect::detect) and it gets mounted as a [`FileSystem`] so the rest of the path can be resolved inside of it.
+
+use crate::{
+    containers::{
+        fs::path_components,
+        nca::Nca,
+        partitionfs::{hfs0::Hfs0, pfs0::Pfs0},
+        romfs::RomFs,
+        xci::Xci,
+        FileSystem,
+    },
+    detect::{detect, ContainerKind},
+    storage::Storage,
+    Integrity, SwonchResult,
+};
+use alloc::boxed::Box;
+use bstr::BString;
+
+#[derive(Debug, thiserror_no_std::Error)]
+pub enum VfsError {
+    #[error("{path} is not a known container, cannot descend into it")]
+    UnknownContainer { path: BString },
+
+    #[error("{path} is a directory, not a file")]
+    PathIsADirectory { path: BString },
+}
+
+/// Opens files inside of arbitrarily nested containers.
+///
+/// ```no_run
+/// use swonch::{prelude::*, storage::FileStorage, vfs::Vfs};
+///
+/// fn main() -> SwonchResult<()> {
+///     let xci = FileStorage::open("game.xci")?;
+///     let file = Vfs::default().open(xci, "secure/abcd.nca/section0/romfs/data/x.bin")?;
+///     println!("{} bytes", file.length()?);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vfs {
+    integrity: Integrity,
+}
+
+impl Vfs {
+    pub fn new(integrity: Integrity) -> Self {
+        Self { integrity }
+    }
+
+    /// Detects the container type of a storage and mounts it, `None` if it isn't a
+    /// container that can be mounted as a [`FileSystem`].
+    pub fn mount(&self, storage: Storage) -> SwonchResult<Option<Box<dyn FileSystem>>> {
+        Ok(Some(match detect(&storage) {
+            Some(ContainerKind::Pfs0) => Box::new(storage.map_to_storage::<Pfs0>(())?),
+            Some(ContainerKind::Hfs0) => Box::new(storage.map_to_storage::<Hfs0>(())?),
+            Some(ContainerKind::Xci) => Box::new(storage.map_to_storage::<Xci>(self.integrity)?),
+            Some(ContainerKind::Nca) => Box::new(storage.map_to_storage::<Nca>(self.integrity)?),
+            Some(ContainerKind::RomFs) => Box::new(storage.map_to_storage::<RomFs>(())?),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Resolves `path` relative to `root`, descending into every container on the way.
+    pub fn open(&self, root: Storage, path: impl AsRef<[u8]>) -> SwonchResult<Storage> {
+        let mut components = path_components(path.as_ref()).peekable();
+        let mut current = root;
+        let mut resolved = BString::default();
+
+        while components.peek().is_some() {
+            let fs = self
+                .mount(current)?
+                .ok_or_else(|| VfsError::UnknownContainer {
+                    path: resolved.clone(),
+                })?;
+
+            // descend through directories until we hit a file, which might be another container
+            let mut inner = BString::default();
+            loop {
+                let Some(name) = components.next() else {
+                    return Err(VfsError::PathIsADirectory { path: resolved }.into());
+                };
+
+                for p in [&mut inner, &mut resolved] {
+                    p.push(b'/');
+                    p.extend_from_slice(name);
+                }
+
+                if fs.metadata(&inner)?.is_file() {
+                    current = fs.open_file(&inner)?;
+                    break;
+                }
+            }
+        }
+
+        Ok(current)
+    }
+
+    /// Like [`Vfs::open`], but the path starts on the host filesystem. The first path component
+    /// that is a regular file on the host is opened and the remainder gets resolved inside of it.
+    #[cfg(feature = "std")]
+    pub fn open_host(&self, path: impl AsRef<std::path::Path>) -> SwonchResult<Storage> {
+        use crate::{containers::fs::FsError, storage::FileStorage};
+        use std::path::PathBuf;
+
+        let path = path.as_ref();
+        let mut host = PathBuf::new();
+        let mut components = path.components();
+
+        while let Some(component) = components.next() {
+            host.push(component);
+            if host.is_file() {
+                let rest = components.as_path().as_os_str().as_encoded_bytes();
+                return self.open(FileStorage::open(&host)?, rest);
+            }
+        }
+
+        Err(FsError::not_a_file(path.as_os_str().as_encoded_bytes()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        containers::{
+            nca::{self, FsType},
+            partitionfs::hfs0::tests::build_hfs0,
+            romfs,
+            xci::tests::build_xci_with,
+        },
+        keyset::KEYS,
+        storage::{IStorage, VecStorage},
+        SwonchError,
+    };
+    use sha2::Digest;
+
+    // an XCI whose secure partition holds a RomFS NCA next to a plain file
+    fn build_nested() -> SwonchResult<Storage> {
+        let mut romfs = romfs::tests::build_romfs(0x50)?;
+        romfs.resize(romfs.len().next_multiple_of(0x200), 0);
+
+        let mut nca = nca::tests::build_nca(&romfs);
+        nca[0x402] = FsType::RomFS as u8;
+        let hash = sha2::Sha256::digest(&nca[0x400..0x600]);
+        nca[0x280..0x2a0].copy_from_slice(&hash);
+
+        let secure = build_hfs0(&[("x.nca", &nca), ("readme.txt", b"not a container")]);
+        Ok(VecStorage::new(build_xci_with(
+            0xfa,
+            &[("update", b""), ("secure", &secure)],
+        )))
+    }
+
+    fn read(storage: &Storage) -> SwonchResult<Vec<u8>> {
+        let mut buf = vec![0; storage.length()? as usize];
+        storage.read_at(0, &mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn open_nested_path() -> SwonchResult<()> {
+        KEYS.insert_key(
+            "key_area_key_application",
+            nca::tests::KEY_AREA_KEY,
+            Some(0),
+        );
+        let vfs = Vfs::new(Integrity::ErrorOnMismatch);
+
+        let file = vfs.open(build_nested()?, "secure/x.nca/section0/romfs/data/bar.bin")?;
+        assert_eq!(read(&file)?, b"bar!");
+
+        let file = vfs.open(build_nested()?, "/secure//x.nca/section0/romfs/foo.bin")?;
+        assert_eq!(read(&file)?, b"foo");
+
+        // an empty path is the root itself
+        let root = build_nested()?;
+        assert_eq!(read(&vfs.open(root.clone(), "")?)?, read(&root)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_is_a_directory() -> SwonchResult<()> {
+        KEYS.insert_key(
+            "key_area_key_application",
+            nca::tests::KEY_AREA_KEY,
+            Some(0),
+        );
+        let vfs = Vfs::default();
+
+        for (path, resolved) in [
+            ("secure/x.nca/section0", "/secure/x.nca/section0"),
+            (
+                "secure/x.nca/section0/romfs/data",
+                "/secure/x.nca/section0/romfs/data",
+            ),
+        ] {
+            assert!(matches!(
+                vfs.open(build_nested()?, path),
+                Err(SwonchError::Vfs(VfsError::PathIsADirectory { path })) if path == resolved
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_container() -> SwonchResult<()> {
+        let vfs = Vfs::default();
+
+        assert!(matches!(
+            vfs.open(build_nested()?, "secure/readme.txt/foo"),
+            Err(SwonchError::Vfs(VfsError::UnknownContainer { path })) if path == "/secure/readme.txt"
+        ));
+
+        // the root itself isn't a container either
+        assert!(matches!(
+            vfs.open(VecStorage::new(b"plain text".to_vec()), "foo"),
+            Err(SwonchError::Vfs(VfsError::UnknownContainer { path })) if path.is_empty()
+        ));
+
+        Ok(())
+    }
+}